@@ -1,29 +1,58 @@
 use crate::aws_credentials::AWScredentials;
-use crate::constants::{PROFILES, PROGRAM_FOLDER};
+use crate::config_plan::ConfigPlan;
+use crate::constants::{PROFILES, PROGRAM_NAME};
+use crate::error::Error;
 use crate::file_helper::{
-    backup_config, get_aws_config, get_exe_path, get_home_os_string, restrict_file_permissions,
+    backup_config, get_aws_config, get_data_file, get_exe_path, restrict_file_permissions,
+    write_file_atomic,
 };
+use crate::profile_sync::save_base;
 use anyhow::{anyhow, Result};
 use ini::Ini;
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+use std::path::Path;
+
+const CREDENTIAL_PROCESS: &str = "credential_process";
+const SSO_MANAGED_KEYS: [&str; 5] = [
+    "sso_start_url",
+    "sso_region",
+    "sso_account_id",
+    "sso_role_name",
+    "duration_seconds",
+];
+const ASSUME_MANAGED_KEYS: [&str; 2] = ["source_profile", "role_arn"];
+
+// Layout version of profiles.json. Bump it together with a new entry in
+// MIGRATIONS whenever Profiles or Profile change shape.
+pub const SCHEMA_VERSION: u64 = 2;
+
+// MIGRATIONS[n] upgrades a version n document to version n + 1.
+type Migration = fn(serde_json::Value) -> Result<serde_json::Value>;
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [migrate_v0_to_v1, migrate_v1_to_v2];
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Profiles {
     pub profiles: HashMap<String, Profile>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+// What to_json writes: the profiles with the schema version alongside.
+#[derive(Serialize)]
+struct VersionedProfiles<'a> {
+    schema_version: u64,
+    #[serde(flatten)]
+    profiles: &'a Profiles,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum Profile {
     SsoProfile(SsoProfile),
     AssumeSsoProfile(AssumeSsoProfile),
     OtherProfile,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
 pub struct SsoProfile {
     pub profile_name: String,
     pub sso_start_url: String,
@@ -32,36 +61,163 @@ pub struct SsoProfile {
     pub sso_role_name: String,
     pub region: Option<String>,
     pub duration_seconds: Option<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eks_clusters: Vec<EksClusterDefault>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
 pub struct AssumeSsoProfile {
     pub source_profile: String,
     pub profile_name: String,
     pub role_arn: String,
     pub region: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eks_clusters: Vec<EksClusterDefault>,
+}
+
+// A cluster `eks` uses for a profile. The first one is the profile's default
+// when --cluster is not given; `context` names it for `eks --context`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct EksClusterDefault {
+    pub cluster: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+}
+
+impl EksClusterDefault {
+    // The name `eks --context` matches, the cluster name unless set.
+    pub fn context_name(&self) -> &str {
+        self.context.as_deref().unwrap_or(&self.cluster)
+    }
+
+    // Comma separated `cluster[@region][=context]` entries, as edited in the
+    // TUI.
+    pub fn parse_list(input: &str) -> Result<Vec<EksClusterDefault>> {
+        let mut clusters = Vec::new();
+        for entry in input.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (entry, context) = match entry.split_once('=') {
+                Some((entry, context)) => (entry.trim(), Some(context.trim())),
+                None => (entry, None),
+            };
+            let (cluster, region) = match entry.split_once('@') {
+                Some((cluster, region)) => (cluster.trim(), Some(region.trim())),
+                None => (entry, None),
+            };
+            let empty = |value: Option<&str>| value.is_some_and(str::is_empty);
+            if cluster.is_empty() || empty(region) || empty(context) {
+                return Err(anyhow!(MyErrors::InvalidEksCluster(entry.to_string())));
+            }
+            clusters.push(EksClusterDefault {
+                cluster: cluster.to_string(),
+                region: region.map(String::from),
+                context: context.map(String::from),
+            });
+        }
+        Ok(clusters)
+    }
+
+    pub fn format_list(clusters: &[EksClusterDefault]) -> String {
+        clusters
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for EksClusterDefault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cluster)?;
+        if let Some(region) = &self.region {
+            write!(f, "@{}", region)?;
+        }
+        if let Some(context) = &self.context {
+            write!(f, "={}", context)?;
+        }
+        Ok(())
+    }
 }
 
 impl Profiles {
+    // The EKS defaults live only in profiles.json, so profiles read back
+    // from the AWS config take them from there.
+    fn eks_clusters_of(&self, profile_name: &str) -> Vec<EksClusterDefault> {
+        match self.profiles.get(profile_name) {
+            Some(profile) => profile.eks_clusters().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    // The profile and cluster `eks --context` names.
+    pub fn find_eks_context(&self, context: &str) -> Result<(String, EksClusterDefault)> {
+        let mut found: Vec<(&String, &EksClusterDefault)> = self
+            .profiles
+            .iter()
+            .flat_map(|(name, p)| p.eks_clusters().iter().map(move |c| (name, c)))
+            .filter(|(_, c)| c.context_name() == context)
+            .collect();
+        found.sort_by_key(|(name, _)| *name);
+        match found.as_slice() {
+            [(name, cluster)] => Ok(((*name).clone(), (*cluster).clone())),
+            [] => Err(anyhow!(MyErrors::EksContextNotFound(context.to_string()))),
+            _ => Err(anyhow!(MyErrors::EksContextAmbiguous(
+                context.to_string(),
+                found
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
     pub fn get_profile(profile_name: String) -> Result<Profile> {
         let profiles = Profiles::from_file()?;
         match profiles.profiles.get(&profile_name) {
             Some(profile) => Ok(profile.clone()),
-            None => Err(anyhow!(MyErrors::ProfileFileNotFound)),
+            None => Err(anyhow!(Error::ProfileNotFound(profile_name))),
         }
     }
 
-    pub fn from_existing_config() -> Result<Profiles> {
+    // The profiles of ~/.aws/config, and the names of the sections still
+    // pointing at ssologinlite whose profile is gone from profiles.json.
+    pub fn from_existing_config() -> Result<(Profiles, Vec<String>)> {
         info!("Reading existing AWS config file");
-        let mut profiles = HashMap::new();
         let aws_config = get_aws_config()?;
         let conf = match Ini::load_from_file(aws_config.as_os_str()) {
             Ok(conf) => conf,
-            Err(_) => {
-                return Err(anyhow!(MyErrors::ProfileFileNotFound));
+            Err(e) => {
+                return Err(anyhow!(Error::ConfigParse {
+                    file: aws_config.to_string_lossy().to_string(),
+                    source: e.into(),
+                })
+                .context(MyErrors::ProfileFileNotFound));
             }
         };
+        // Sections already pointing at ssologinlite only keep region and
+        // unmanaged keys; their SSO details live in profiles.json.
+        let managed = match Profiles::from_file() {
+            Ok(managed) => managed,
+            Err(e) => {
+                debug!(
+                    "aws_profile.Profiles.from_existing_config no profiles file: {}",
+                    e
+                );
+                Profiles::default()
+            }
+        };
+        Profiles::from_ini(&conf, &managed)
+    }
 
+    // Parse the profiles defined in an AWS config. Native SSO / assume-role
+    // sections are read as-is; sections whose credential_process is ours are
+    // rebuilt from `managed`, with the region the config still carries. Those
+    // `managed` no longer has are returned by name as orphans.
+    pub fn from_ini(conf: &Ini, managed: &Profiles) -> Result<(Profiles, Vec<String>)> {
+        let mut profiles = HashMap::new();
+        let mut orphans = Vec::new();
         for (profile_name, profile) in conf.iter() {
             debug!(
                 "aws_profile.Profiles.from_existing_config looping into {:?}",
@@ -132,13 +288,14 @@ impl Profiles {
                         profiles.insert(
                             key.clone(),
                             Profile::SsoProfile(SsoProfile {
-                                profile_name: key,
+                                profile_name: key.clone(),
                                 sso_start_url,
                                 sso_region,
                                 sso_account_id,
                                 sso_role_name,
                                 region: profile.get("region").map(|region| region.to_string()),
                                 duration_seconds,
+                                eks_clusters: managed.eks_clusters_of(&key),
                             }),
                         );
                     } else if profile.contains_key("source_profile")
@@ -173,12 +330,41 @@ impl Profiles {
                         profiles.insert(
                             key.clone(),
                             Profile::AssumeSsoProfile(AssumeSsoProfile {
-                                profile_name: key,
+                                profile_name: key.clone(),
                                 source_profile,
                                 role_arn,
                                 region,
+                                eks_clusters: managed.eks_clusters_of(&key),
                             }),
                         );
+                    } else if is_managed_section(profile) {
+                        let key: String = profile_name.replace("profile ", "");
+                        let region = profile.get("region").map(|region| region.to_string());
+                        match managed.profiles.get(&key) {
+                            Some(Profile::SsoProfile(p)) => {
+                                debug!("Inserting managed {}", profile_name);
+                                let mut p = p.clone();
+                                if region.is_some() {
+                                    p.region = region;
+                                }
+                                profiles.insert(key, Profile::SsoProfile(p));
+                            }
+                            Some(Profile::AssumeSsoProfile(p)) => {
+                                debug!("Inserting managed {}", profile_name);
+                                let mut p = p.clone();
+                                if let Some(region) = region {
+                                    p.region = region;
+                                }
+                                profiles.insert(key, Profile::AssumeSsoProfile(p));
+                            }
+                            _ => {
+                                info!(
+                                    "aws_profiles.Profiles.from_ini {} points at ssologinlite but is not in profiles.json",
+                                    profile_name
+                                );
+                                orphans.push(key);
+                            }
+                        }
                     };
                 }
                 None => {
@@ -186,7 +372,7 @@ impl Profiles {
                 }
             }
         }
-        Ok(Profiles { profiles })
+        Ok((Profiles { profiles }, orphans))
     }
 
     pub fn from_url(&self, url: &str) -> Option<&Profile> {
@@ -209,77 +395,78 @@ impl Profiles {
 
     pub fn to_file(&self) -> Result<()> {
         info!("Writing profiles to my own managed file");
-        let profile_json = get_home_os_string(format!("{}/{}", PROGRAM_FOLDER, PROFILES).as_str())?;
-        restrict_file_permissions(&profile_json)?;
-        let mut file = match File::create(profile_json.clone()) {
-            Ok(file) => file,
-            Err(e) => {
-                error!("aws_profiles.Profiles.to_file {:?}", e);
-                return Err(anyhow!("Error creating profile file"));
-            }
-        };
-        let data: String = match serde_json::to_string(self) {
+        let profile_json = get_data_file(PROFILES)?;
+        let data = match self.to_json() {
             Ok(data) => data,
             Err(e) => {
                 error!("aws_profiles.Profiles.to_file {:?}", e);
                 return Err(anyhow!("Error serializing profile"));
             }
         };
-        match file.write_all(data.as_bytes()) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("aws_profiles.Profiles.to_file {:?}", e);
-                return Err(anyhow!("Error writing to profile file"));
-            }
-        };
-        restrict_file_permissions(&profile_json)?;
-        Ok(())
+        write_file_atomic(&profile_json, data.as_bytes())
     }
 
+    // Files written with an older schema are upgraded in place on read.
     pub fn from_file() -> Result<Profiles> {
         info!("Reading profiles from my own managed file");
-        let profile_json = get_home_os_string(format!("{}/{}", PROGRAM_FOLDER, PROFILES).as_str())?;
+        let profile_json = get_data_file(PROFILES)?;
         debug!("from_file.profile_json = {:?}", profile_json);
-        restrict_file_permissions(&profile_json)?;
-        let file = File::open(profile_json)?;
-        debug!("from_file.file = {:?}", file);
-        let profiles: Profiles = serde_json::from_reader(file)?;
+        let config_parse = |e: anyhow::Error| Error::ConfigParse {
+            file: profile_json.to_string_lossy().to_string(),
+            source: e.into(),
+        };
+        restrict_file_permissions(&profile_json).map_err(config_parse)?;
+        let data = std::fs::read_to_string(&profile_json).map_err(|e| config_parse(e.into()))?;
+        let (profiles, version) = Profiles::from_json(&data).map_err(config_parse)?;
         debug!("from_file.profiles = {:?}", profiles);
+        if version < SCHEMA_VERSION {
+            info!(
+                "Upgrading profiles file from schema {} to {}",
+                version, SCHEMA_VERSION
+            );
+            if let Err(e) = profiles.to_file() {
+                error!("aws_profiles.Profiles.from_file {}", e);
+            }
+        }
         Ok(profiles)
     }
 
-    pub fn setup_file() -> Result<()> {
-        info!("Setting up profiles file");
-        backup_config()?;
-
-        let existing_profiles = Profiles::from_existing_config()?;
-
-        let profile_json = get_home_os_string(format!("{}/{}", PROGRAM_FOLDER, PROFILES).as_str())?;
-        let _ = File::create(&profile_json)?;
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&VersionedProfiles {
+            schema_version: SCHEMA_VERSION,
+            profiles: self,
+        })?)
+    }
 
-        let mut file = match File::create(&profile_json) {
-            Ok(file) => file,
-            Err(e) => {
-                error!("aws_profiles.Profiles.setup_file {:?}", e);
-                return Err(anyhow!("Error creating profile file"));
-            }
-        };
-        restrict_file_permissions(&profile_json)?;
-        let data: String = match serde_json::to_string(&existing_profiles) {
-            Ok(data) => data,
-            Err(e) => {
-                error!("aws_profiles.Profiles.setup_file {:?}", e);
-                return Err(anyhow!("Error serializing profile file"));
-            }
-        };
-        match file.write_all(data.as_bytes()) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("aws_profiles.Profiles.setup_file {:?}", e);
-                return Err(anyhow!("Error writing to profile file"));
-            }
+    // Parse profiles.json in any known layout. Returns the profiles and the
+    // schema version the data was written with.
+    pub fn from_json(data: &str) -> Result<(Profiles, u64)> {
+        let mut doc: serde_json::Value = serde_json::from_str(data)?;
+        let version = match doc.get("schema_version") {
+            None => 0,
+            Some(version) => match version.as_u64() {
+                Some(version) => version,
+                None => return Err(anyhow!(MyErrors::InvalidSchema)),
+            },
         };
+        if version > SCHEMA_VERSION {
+            error!(
+                "aws_profiles.Profiles.from_json schema {} is newer than {}",
+                version, SCHEMA_VERSION
+            );
+            return Err(anyhow!(MyErrors::UnsupportedSchema(version)));
+        }
+        for migrate in MIGRATIONS[version as usize..].iter() {
+            doc = migrate(doc)?;
+        }
+        let profiles = Profiles::deserialize(doc)?;
+        Ok((profiles, version))
+    }
 
+    // Compute the profiles setup would import and the ~/.aws/config edits
+    // it would make, without touching disk.
+    pub fn plan_setup() -> Result<(Profiles, ConfigPlan)> {
+        let (existing_profiles, _) = Profiles::from_existing_config()?;
         let exe_path_os_str = get_exe_path()?;
         let exe_path = match exe_path_os_str.as_os_str().to_str() {
             Some(exe_path) => exe_path,
@@ -287,51 +474,231 @@ impl Profiles {
                 return Err(anyhow!(MyErrors::ExePathError));
             }
         };
+        let plan = ConfigPlan::load_aws_config()?
+            .edit(|conf| existing_profiles.apply_setup(conf, exe_path))?;
+        Ok((existing_profiles, plan))
+    }
+
+    pub fn setup_file() -> Result<()> {
+        info!("Setting up profiles file");
+        backup_config()?;
+
+        let (existing_profiles, plan) = Profiles::plan_setup()?;
+
+        existing_profiles.to_file()?;
 
         debug!("aws_profiles.Profiles.setup_file writing config");
-        let aws_config = get_aws_config()?;
-        let mut conf = match Ini::load_from_file(aws_config.as_os_str()) {
-            Ok(conf) => conf,
-            Err(_) => {
-                return Err(anyhow!(MyErrors::ProfileFileNotFound));
+        plan.apply()?;
+        save_base(&existing_profiles)
+    }
+
+    // Point every known profile section at credential_process. Only the keys
+    // ssologinlite takes over are removed; region, cli_pager, s3 settings and
+    // anything else the user put in the section are kept.
+    pub fn apply_setup(&self, conf: &mut Ini, exe_path: &str) -> Result<()> {
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        for profile_name in names {
+            debug!(
+                "aws_profiles.Profiles.apply_setup writing profile {}",
+                profile_name
+            );
+            let ini_profile = config_section_name(profile_name);
+            if conf.section(Some(&ini_profile)).is_none() {
+                return Err(anyhow!("Section '{}' not found", ini_profile));
+            }
+            self.profiles[profile_name].set_managed_section(conf, profile_name, exe_path);
+        }
+        Ok(())
+    }
+
+    // Inverse of apply_setup: drop credential_process and write the native
+    // SSO / assume-role keys back from profiles.json. Sections that no longer
+    // exist in the config are recreated.
+    pub fn apply_unsetup(&self, conf: &mut Ini) {
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        for profile_name in names {
+            let profile = &self.profiles[profile_name];
+            if matches!(profile, Profile::OtherProfile) {
+                continue;
             }
-        };
-        for profile in existing_profiles.profiles.keys() {
             debug!(
-                "aws_profiles.Profiles.setup_file writing profile {}",
-                profile
+                "aws_profiles.Profiles.apply_unsetup restoring profile {}",
+                profile_name
             );
-            let ini_profile = match profile == "default" {
-                true => "default".to_string(),
-                _ => format!("profile {}", profile),
-            };
-            // let ini_profile = format!("profile {}", profile);
-            let common_args = ["--profile".to_string(), profile.clone()];
-            let section = match conf.section(Some(&ini_profile)) {
-                Some(section) => section.clone(),
-                None => {
-                    return Err(anyhow!("Section '{}' not found", ini_profile));
+            let ini_profile = config_section_name(profile_name);
+            if is_foreign_section(conf, &ini_profile) {
+                error!(
+                    "aws_profiles.Profiles.apply_unsetup {} runs another credential_process, skipping",
+                    profile_name
+                );
+                continue;
+            }
+            if let Some(section) = conf.section_mut(Some(&ini_profile)) {
+                section.remove_all(CREDENTIAL_PROCESS).for_each(drop);
+            }
+            profile.set_native_section(conf, &ini_profile);
+        }
+    }
+
+    pub fn plan_unsetup() -> Result<ConfigPlan> {
+        let profiles = Profiles::from_file()?;
+        ConfigPlan::load_aws_config()?.edit(|conf| {
+            profiles.apply_unsetup(conf);
+            Ok(())
+        })
+    }
+
+    pub fn unsetup_file() -> Result<()> {
+        info!("Restoring native SSO profiles in aws config");
+        backup_config()?;
+        Profiles::plan_unsetup()?.apply()
+    }
+}
+
+// True when the section's credential_process already calls ssologinlite.
+fn is_managed_section(section: &ini::Properties) -> bool {
+    match section.get(CREDENTIAL_PROCESS) {
+        Some(process) => is_own_process(process),
+        None => false,
+    }
+}
+
+// True when the section runs another tool's credential_process, which setup,
+// sync and unsetup leave alone.
+fn is_foreign_section(conf: &Ini, section_name: &str) -> bool {
+    match conf
+        .section(Some(section_name))
+        .and_then(|section| section.get(CREDENTIAL_PROCESS))
+    {
+        Some(process) => !is_own_process(process),
+        None => false,
+    }
+}
+
+// "<exe> token --profile <name>" as set_managed_section writes it, <exe>
+// being ssologinlite wherever it is installed.
+fn is_own_process(process: &str) -> bool {
+    let Some((exe, _)) = process.split_once(" token --profile ") else {
+        return false;
+    };
+    let exe = exe.trim().trim_matches('"');
+    let is_current_exe = get_exe_path().is_ok_and(|current| current == exe);
+    is_current_exe
+        || Path::new(exe)
+            .file_stem()
+            .is_some_and(|stem| stem == PROGRAM_NAME)
+}
+
+// The default profile uses [default], not [profile default], in
+// ~/.aws/config.
+pub fn config_section_name(profile_name: &str) -> String {
+    match profile_name == "default" {
+        true => "default".to_string(),
+        _ => format!("profile {}", profile_name),
+    }
+}
+
+impl Profile {
+    // Keys ssologinlite owns once a section points at credential_process.
+    fn managed_keys(&self) -> &'static [&'static str] {
+        match self {
+            Profile::SsoProfile(_) => &SSO_MANAGED_KEYS,
+            Profile::AssumeSsoProfile(_) => &ASSUME_MANAGED_KEYS,
+            Profile::OtherProfile => &[],
+        }
+    }
+
+    // Point the profile's section at credential_process, creating it if
+    // needed. Managed keys are removed, region is written from the profile and
+    // every other key is kept.
+    pub fn set_managed_section(&self, conf: &mut Ini, profile_name: &str, exe_path: &str) {
+        if let Profile::OtherProfile = self {
+            return;
+        }
+        let region = self.region();
+        let ini_profile = config_section_name(profile_name);
+        if is_foreign_section(conf, &ini_profile) {
+            error!(
+                "aws_profiles.Profile.set_managed_section {} runs another credential_process, skipping",
+                profile_name
+            );
+            return;
+        }
+        let section = conf
+            .entry(Some(ini_profile))
+            .or_insert_with(ini::Properties::new);
+        for key in self.managed_keys() {
+            section.remove_all(key).for_each(drop);
+        }
+        let credential_process = format!(r#"{exe_path} token --profile {profile_name}"#);
+        section.insert(CREDENTIAL_PROCESS, credential_process);
+        if let Some(region) = region {
+            section.insert("region", region);
+        }
+        if !section.contains_key("output") {
+            section.insert("output", "json");
+        }
+    }
+
+    // Write the profile's native AWS CLI keys (no credential_process) into
+    // `section`, creating it if needed.
+    pub fn set_native_section(&self, conf: &mut Ini, section: &str) {
+        match self {
+            Profile::SsoProfile(p) => {
+                let mut sec = conf.with_section(Some(section));
+                sec.set("sso_start_url", p.sso_start_url.as_str())
+                    .set("sso_region", p.sso_region.as_str())
+                    .set("sso_account_id", p.sso_account_id.as_str())
+                    .set("sso_role_name", p.sso_role_name.as_str());
+                if let Some(r) = &p.region {
+                    sec.set("region", r.as_str());
+                }
+                if let Some(d) = p.duration_seconds {
+                    sec.set("duration_seconds", d.to_string().as_str());
                 }
-            };
-            for (k, _) in section.iter() {
-                match conf.section_mut(Some(&ini_profile)) {
-                    Some(section) => {
-                        section.remove(k);
-                    }
-                    None => {
-                        return Err(anyhow!(MyErrors::ProfileFileNotFound));
-                    }
-                };
             }
-            let credential_process =
-                format!(r#"{exe_path} token {args}"#, args = common_args.join(" "));
-            conf.with_section(Some(&ini_profile))
-                .set("credential_process", credential_process.as_str())
-                .set("output", "json");
-        }
-        // debug!("{:?}", conf);
-        conf.write_to_file(aws_config.as_os_str())?;
-        Ok(())
+            Profile::AssumeSsoProfile(p) => {
+                conf.with_section(Some(section))
+                    .set("source_profile", p.source_profile.as_str())
+                    .set("role_arn", p.role_arn.as_str())
+                    .set("region", p.region.as_str());
+            }
+            Profile::OtherProfile => {}
+        }
+    }
+
+    pub fn eks_clusters(&self) -> &[EksClusterDefault] {
+        match self {
+            Profile::SsoProfile(p) => &p.eks_clusters,
+            Profile::AssumeSsoProfile(p) => &p.eks_clusters,
+            Profile::OtherProfile => &[],
+        }
+    }
+
+    // The configured entry for `cluster`, or the profile's default cluster.
+    pub fn eks_cluster(&self, cluster: Option<&str>) -> Option<&EksClusterDefault> {
+        match cluster {
+            Some(cluster) => self.eks_clusters().iter().find(|c| c.cluster == cluster),
+            None => self.eks_clusters().first(),
+        }
+    }
+
+    pub fn region(&self) -> Option<String> {
+        match self {
+            Profile::SsoProfile(p) => p.region.clone(),
+            Profile::AssumeSsoProfile(p) => Some(p.region.clone()),
+            Profile::OtherProfile => None,
+        }
+    }
+
+    pub async fn get_credentials(&self) -> Result<AWScredentials> {
+        match self {
+            Profile::SsoProfile(profile) => profile.get_credentials().await,
+            Profile::AssumeSsoProfile(profile) => profile.get_credentials().await,
+            Profile::OtherProfile => Err(anyhow!(MyErrors::UnmanagedProfile)),
+        }
     }
 }
 
@@ -341,7 +708,7 @@ impl SsoProfile {
         let profiles = Profiles::from_file()?;
         match profiles.profiles.get(&profile_name) {
             Some(Profile::SsoProfile(sso_profile)) => Ok(sso_profile.clone()),
-            _ => Err(anyhow!("Profile not found")),
+            _ => Err(anyhow!(Error::ProfileNotFound(profile_name))),
         }
     }
     pub async fn get_token(&self) -> Result<String> {
@@ -361,7 +728,7 @@ impl AssumeSsoProfile {
         let profiles = Profiles::from_file()?;
         match profiles.profiles.get(&profile_name) {
             Some(Profile::AssumeSsoProfile(assume_profile)) => Ok(assume_profile.clone()),
-            _ => Err(anyhow!("Profile {} not found", &profile_name)),
+            _ => Err(anyhow!(Error::ProfileNotFound(profile_name))),
         }
     }
     pub fn get_sso_profile(&self) -> Result<SsoProfile> {
@@ -372,10 +739,8 @@ impl AssumeSsoProfile {
         );
         match profiles.profiles.get(&self.source_profile) {
             Some(Profile::SsoProfile(sso_profile)) => Ok(sso_profile.clone()),
-            _ => Err(anyhow!(
-                "Associated sso profile for {} not found",
-                &self.source_profile
-            )),
+            _ => Err(anyhow!(Error::ProfileNotFound(self.source_profile.clone()))
+                .context(MyErrors::SourceProfileNotFound(self.profile_name.clone()))),
         }
     }
     pub async fn get_token(&self) -> Result<String> {
@@ -391,17 +756,68 @@ impl AssumeSsoProfile {
     }
 }
 
+// v0 is the unversioned {"profiles": {...}} layout written up to 0.4.0.
+fn migrate_v0_to_v1(doc: serde_json::Value) -> Result<serde_json::Value> {
+    set_schema_version(doc, 1)
+}
+
+// v2 adds eks_clusters to SsoProfile and AssumeSsoProfile. v1 profiles have
+// none, which is what the missing field reads as.
+fn migrate_v1_to_v2(doc: serde_json::Value) -> Result<serde_json::Value> {
+    set_schema_version(doc, 2)
+}
+
+fn set_schema_version(mut doc: serde_json::Value, version: u64) -> Result<serde_json::Value> {
+    match doc.as_object_mut() {
+        Some(object) => {
+            object.insert("schema_version".to_string(), version.into());
+            Ok(doc)
+        }
+        None => Err(anyhow!(MyErrors::InvalidSchema)),
+    }
+}
+
 #[derive(Debug)]
 enum MyErrors {
     ProfileFileNotFound,
+    UnmanagedProfile,
+    SourceProfileNotFound(String),
     ExePathError,
+    InvalidSchema,
+    UnsupportedSchema(u64),
+    InvalidEksCluster(String),
+    EksContextNotFound(String),
+    EksContextAmbiguous(String, String),
 }
 
 impl std::fmt::Display for MyErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::ProfileFileNotFound => write!(f, "Could not find aws config file"),
+            Self::UnmanagedProfile => write!(f, "Profile is not managed by ssologinlite"),
+            Self::SourceProfileNotFound(profile) => {
+                write!(f, "Associated sso profile for {} not found", profile)
+            }
             Self::ExePathError => write!(f, "Could not get exe path"),
+            Self::InvalidSchema => write!(f, "Profiles file has an unknown layout"),
+            Self::UnsupportedSchema(version) => write!(
+                f,
+                "Profiles file schema {} is newer than this version supports ({}), please upgrade",
+                version, SCHEMA_VERSION
+            ),
+            Self::InvalidEksCluster(entry) => write!(
+                f,
+                "Invalid EKS cluster '{}', expected cluster[@region][=context]",
+                entry
+            ),
+            Self::EksContextNotFound(context) => {
+                write!(f, "No profile has an EKS cluster named '{}'", context)
+            }
+            Self::EksContextAmbiguous(context, profiles) => write!(
+                f,
+                "EKS context '{}' is defined by several profiles: {}",
+                context, profiles
+            ),
         }
     }
 }
@@ -419,6 +835,7 @@ mod tests {
             sso_role_name: "AdminRole".to_string(),
             region: Some("us-west-2".to_string()),
             duration_seconds: Some(3600),
+            eks_clusters: Vec::new(),
         }
     }
 
@@ -428,6 +845,7 @@ mod tests {
             profile_name: name.to_string(),
             role_arn: "arn:aws:iam::123456789012:role/MyRole".to_string(),
             region: "us-east-1".to_string(),
+            eks_clusters: Vec::new(),
         }
     }
 
@@ -606,6 +1024,205 @@ mod tests {
         assert_eq!(p.region, "");
     }
 
+    // --- apply_setup() / apply_unsetup() ---
+
+    const NATIVE_CONFIG: &str = "[profile dev]
+sso_start_url = https://my-sso.awsapps.com/start
+sso_region = us-west-2
+sso_account_id = 123456789012
+sso_role_name = AdminRole
+region = us-west-2
+duration_seconds = 3600
+cli_pager =
+
+[profile assume-prod]
+source_profile = dev
+role_arn = arn:aws:iam::123456789012:role/MyRole
+region = us-east-1
+output = text
+
+[profile static]
+aws_access_key_id = AK
+";
+
+    fn make_setup_profiles() -> Profiles {
+        let mut profiles = HashMap::new();
+        profiles.insert(
+            "dev".to_string(),
+            Profile::SsoProfile(make_sso_profile("dev", "https://my-sso.awsapps.com/start")),
+        );
+        profiles.insert(
+            "assume-prod".to_string(),
+            Profile::AssumeSsoProfile(make_assume_profile("assume-prod")),
+        );
+        Profiles { profiles }
+    }
+
+    #[test]
+    fn test_apply_setup_keeps_unknown_keys() {
+        let mut conf = Ini::load_from_str(NATIVE_CONFIG).unwrap();
+        make_setup_profiles()
+            .apply_setup(&mut conf, "/bin/ssologinlite")
+            .unwrap();
+        let dev = conf.section(Some("profile dev")).unwrap();
+        assert_eq!(
+            dev.get("credential_process"),
+            Some("/bin/ssologinlite token --profile dev")
+        );
+        assert_eq!(dev.get("region"), Some("us-west-2"));
+        assert_eq!(dev.get("cli_pager"), Some(""));
+        assert_eq!(dev.get("output"), Some("json"));
+        for key in SSO_MANAGED_KEYS {
+            assert!(!dev.contains_key(key), "{key} should be removed");
+        }
+        let assume = conf.section(Some("profile assume-prod")).unwrap();
+        assert_eq!(assume.get("region"), Some("us-east-1"));
+        assert_eq!(assume.get("output"), Some("text"));
+        assert!(!assume.contains_key("source_profile"));
+        assert!(!assume.contains_key("role_arn"));
+        assert_eq!(
+            conf.section(Some("profile static"))
+                .unwrap()
+                .get("aws_access_key_id"),
+            Some("AK")
+        );
+    }
+
+    #[test]
+    fn test_apply_setup_missing_section_errors() {
+        let mut conf = Ini::load_from_str("[profile static]\nregion = us-east-1\n").unwrap();
+        assert!(make_setup_profiles()
+            .apply_setup(&mut conf, "/bin/ssologinlite")
+            .is_err());
+    }
+
+    #[test]
+    fn test_apply_unsetup_round_trip() {
+        let mut conf = Ini::load_from_str(NATIVE_CONFIG).unwrap();
+        let profiles = make_setup_profiles();
+        profiles
+            .apply_setup(&mut conf, "/bin/ssologinlite")
+            .unwrap();
+        profiles.apply_unsetup(&mut conf);
+        let original = Ini::load_from_str(NATIVE_CONFIG).unwrap();
+        for section in ["profile dev", "profile assume-prod"] {
+            let restored = conf.section(Some(section)).unwrap();
+            assert!(!restored.contains_key("credential_process"));
+            for (k, v) in original.section(Some(section)).unwrap().iter() {
+                assert_eq!(restored.get(k), Some(v), "{section}: {k}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_unsetup_recreates_missing_section() {
+        let mut conf = Ini::new();
+        make_setup_profiles().apply_unsetup(&mut conf);
+        let dev = conf.section(Some("profile dev")).unwrap();
+        assert_eq!(dev.get("sso_account_id"), Some("123456789012"));
+    }
+
+    // --- from_ini() ---
+
+    #[test]
+    fn test_from_ini_native_sections() {
+        let conf = Ini::load_from_str(NATIVE_CONFIG).unwrap();
+        let (profiles, _) = Profiles::from_ini(&conf, &Profiles::default()).unwrap();
+        assert_eq!(profiles.profiles.len(), 2);
+        assert_eq!(
+            profiles.profiles["dev"],
+            Profile::SsoProfile(make_sso_profile("dev", "https://my-sso.awsapps.com/start"))
+        );
+        assert_eq!(
+            profiles.profiles["assume-prod"],
+            Profile::AssumeSsoProfile(make_assume_profile("assume-prod"))
+        );
+    }
+
+    #[test]
+    fn test_from_ini_managed_sections() {
+        let mut conf = Ini::load_from_str(NATIVE_CONFIG).unwrap();
+        let managed = make_setup_profiles();
+        managed.apply_setup(&mut conf, "/bin/ssologinlite").unwrap();
+        conf.with_section(Some("profile dev"))
+            .set("region", "eu-central-1");
+        let (profiles, _) = Profiles::from_ini(&conf, &managed).unwrap();
+        assert_eq!(profiles.profiles.len(), 2);
+        match &profiles.profiles["dev"] {
+            Profile::SsoProfile(p) => {
+                assert_eq!(p.sso_account_id, "123456789012");
+                assert_eq!(p.region.as_deref(), Some("eu-central-1"));
+            }
+            _ => panic!("expected SsoProfile"),
+        }
+        assert_eq!(
+            profiles.profiles["assume-prod"],
+            managed.profiles["assume-prod"]
+        );
+    }
+
+    #[test]
+    fn test_from_ini_managed_unknown_is_orphaned() {
+        let conf = Ini::load_from_str(
+            "[profile ghost]\ncredential_process = /bin/ssologinlite token --profile ghost\n",
+        )
+        .unwrap();
+        let (profiles, orphans) = Profiles::from_ini(&conf, &Profiles::default()).unwrap();
+        assert!(profiles.profiles.is_empty());
+        assert_eq!(orphans, vec!["ghost".to_string()]);
+    }
+
+    #[test]
+    fn test_foreign_credential_process_is_not_managed() {
+        let text = "[profile vault]\ncredential_process = /usr/bin/aws-vault token --profile vault\n\n\
+                    [profile ours]\ncredential_process = \"/opt/my tools/ssologinlite\" token --profile ours\n";
+        let mut conf = Ini::load_from_str(text).unwrap();
+        assert!(is_foreign_section(&conf, "profile vault"));
+        assert!(!is_foreign_section(&conf, "profile ours"));
+        assert!(!is_foreign_section(&conf, "profile missing"));
+
+        let mut managed = make_setup_profiles();
+        let dev = managed.profiles.remove("dev").unwrap();
+        managed.profiles.insert("vault".to_string(), dev);
+        let (profiles, _) = Profiles::from_ini(&conf, &managed).unwrap();
+        assert!(!matches!(
+            profiles.profiles.get("vault"),
+            Some(Profile::SsoProfile(_))
+        ));
+
+        managed.profiles["vault"].set_managed_section(&mut conf, "vault", "/bin/ssologinlite");
+        managed.apply_unsetup(&mut conf);
+        let vault = conf.section(Some("profile vault")).unwrap();
+        assert_eq!(
+            vault.get("credential_process"),
+            Some("/usr/bin/aws-vault token --profile vault")
+        );
+        assert!(!vault.contains_key("sso_start_url"));
+    }
+
+    #[test]
+    fn test_set_managed_section_creates_section() {
+        let mut conf = Ini::new();
+        Profile::AssumeSsoProfile(make_assume_profile("assume-prod")).set_managed_section(
+            &mut conf,
+            "assume-prod",
+            "/bin/ssologinlite",
+        );
+        let section = conf.section(Some("profile assume-prod")).unwrap();
+        assert_eq!(
+            section.get("credential_process"),
+            Some("/bin/ssologinlite token --profile assume-prod")
+        );
+        assert_eq!(section.get("region"), Some("us-east-1"));
+        assert!(!section.contains_key("role_arn"));
+    }
+
+    #[test]
+    fn test_config_section_name() {
+        assert_eq!(config_section_name("default"), "default");
+        assert_eq!(config_section_name("dev"), "profile dev");
+    }
+
     // --- MyErrors Display ---
 
     #[test]
@@ -616,6 +1233,142 @@ mod tests {
         );
     }
 
+    // --- Schema versions (golden files) ---
+
+    const GOLDEN_V0: &str = include_str!("../testdata/profiles/v0.json");
+    const GOLDEN_V0_PRETTY: &str = include_str!("../testdata/profiles/v0_pretty.json");
+    const GOLDEN_V1: &str = include_str!("../testdata/profiles/v1.json");
+    const GOLDEN_V2: &str = include_str!("../testdata/profiles/v2.json");
+
+    fn make_golden_profiles() -> Profiles {
+        let mut profiles = make_profiles();
+        profiles.profiles.remove("staging");
+        profiles.profiles.insert(
+            "legacy".to_string(),
+            Profile::SsoProfile(SsoProfile {
+                profile_name: "legacy".to_string(),
+                sso_start_url: "https://my-sso.awsapps.com/start".to_string(),
+                sso_region: "us-west-2".to_string(),
+                sso_account_id: "210987654321".to_string(),
+                sso_role_name: "ReadOnly".to_string(),
+                region: None,
+                duration_seconds: None,
+                eks_clusters: Vec::new(),
+            }),
+        );
+        profiles
+            .profiles
+            .insert("other".to_string(), Profile::OtherProfile);
+        profiles
+    }
+
+    #[test]
+    fn test_from_json_golden_v0() {
+        let (profiles, version) = Profiles::from_json(GOLDEN_V0).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(profiles.profiles, make_golden_profiles().profiles);
+    }
+
+    #[test]
+    fn test_from_json_golden_v0_pretty() {
+        let (profiles, version) = Profiles::from_json(GOLDEN_V0_PRETTY).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(profiles.profiles, make_golden_profiles().profiles);
+    }
+
+    #[test]
+    fn test_from_json_golden_v1() {
+        let (profiles, version) = Profiles::from_json(GOLDEN_V1).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(profiles.profiles, make_golden_profiles().profiles);
+    }
+
+    // make_golden_profiles with the EKS clusters v2 added.
+    fn make_golden_v2_profiles() -> Profiles {
+        let mut profiles = make_golden_profiles();
+        if let Some(Profile::SsoProfile(dev)) = profiles.profiles.get_mut("dev") {
+            dev.eks_clusters =
+                EksClusterDefault::parse_list("main@eu-west-1=prod-main, dev").unwrap();
+        }
+        if let Some(Profile::AssumeSsoProfile(prod)) = profiles.profiles.get_mut("assume-prod") {
+            prod.eks_clusters = EksClusterDefault::parse_list("ops").unwrap();
+        }
+        profiles
+    }
+
+    #[test]
+    fn test_from_json_golden_v2() {
+        let (profiles, version) = Profiles::from_json(GOLDEN_V2).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(profiles.profiles, make_golden_v2_profiles().profiles);
+    }
+
+    #[test]
+    fn test_to_json_matches_current_golden() {
+        let golden: serde_json::Value = serde_json::from_str(GOLDEN_V2).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&make_golden_v2_profiles().to_json().unwrap()).unwrap();
+        assert_eq!(written, golden);
+    }
+
+    #[test]
+    fn test_old_goldens_upgrade_to_current_schema() {
+        for old in [GOLDEN_V0, GOLDEN_V0_PRETTY, GOLDEN_V1] {
+            let (profiles, _) = Profiles::from_json(old).unwrap();
+            let (upgraded, version) = Profiles::from_json(&profiles.to_json().unwrap()).unwrap();
+            assert_eq!(version, SCHEMA_VERSION);
+            assert_eq!(upgraded.profiles, make_golden_profiles().profiles);
+        }
+    }
+
+    #[test]
+    fn test_migrations_cover_every_version() {
+        assert_eq!(MIGRATIONS.len() as u64, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_from_json_newer_schema_errors() {
+        let err = Profiles::from_json(r#"{"schema_version":99,"profiles":{}}"#).unwrap_err();
+        assert!(err.to_string().contains("schema 99"));
+    }
+
+    #[test]
+    fn test_from_json_invalid_schema_errors() {
+        assert!(Profiles::from_json(r#"{"schema_version":"1","profiles":{}}"#).is_err());
+        assert!(Profiles::from_json("[]").is_err());
+    }
+
+    #[test]
+    #[serial_test::serial(env_vars)]
+    fn test_from_file_upgrades_old_schema() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let saved = std::env::var_os("HOME");
+        std::env::set_var("HOME", tmp.path());
+        let path = tmp
+            .path()
+            .join(crate::constants::PROGRAM_FOLDER)
+            .join(PROFILES);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, GOLDEN_V0).unwrap();
+        let result = Profiles::from_file();
+        let written = std::fs::read_to_string(&path);
+        match saved {
+            Some(v) => std::env::set_var("HOME", v),
+            None => std::env::remove_var("HOME"),
+        }
+        assert_eq!(result.unwrap().profiles, make_golden_profiles().profiles);
+        let (_, version) = Profiles::from_json(&written.unwrap()).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_error_display_unsupported_schema() {
+        assert_eq!(
+            format!("{}", MyErrors::UnsupportedSchema(3)),
+            "Profiles file schema 3 is newer than this version supports (2), please upgrade"
+        );
+    }
+
     #[test]
     fn test_error_display_exe_path() {
         assert_eq!(
@@ -623,4 +1376,119 @@ mod tests {
             "Could not get exe path"
         );
     }
+
+    fn eks(cluster: &str, region: Option<&str>, context: Option<&str>) -> EksClusterDefault {
+        EksClusterDefault {
+            cluster: cluster.to_string(),
+            region: region.map(String::from),
+            context: context.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_eks_cluster_parse_list() {
+        let clusters =
+            EksClusterDefault::parse_list(" main , batch@eu-west-1, ops@us-east-1=ops-east,")
+                .unwrap();
+        assert_eq!(
+            clusters,
+            vec![
+                eks("main", None, None),
+                eks("batch", Some("eu-west-1"), None),
+                eks("ops", Some("us-east-1"), Some("ops-east")),
+            ]
+        );
+        assert_eq!(
+            EksClusterDefault::format_list(&clusters),
+            "main, batch@eu-west-1, ops@us-east-1=ops-east"
+        );
+        assert_eq!(clusters[1].context_name(), "batch");
+        assert_eq!(clusters[2].context_name(), "ops-east");
+        assert!(EksClusterDefault::parse_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_eks_cluster_parse_list_invalid() {
+        assert!(EksClusterDefault::parse_list("@us-east-1").is_err());
+        assert!(EksClusterDefault::parse_list("main@").is_err());
+        assert!(EksClusterDefault::parse_list("main=").is_err());
+    }
+
+    #[test]
+    fn test_profile_eks_cluster_default() {
+        let mut sso = make_sso_profile("dev", "https://my-sso.awsapps.com/start");
+        assert!(Profile::SsoProfile(sso.clone()).eks_cluster(None).is_none());
+        sso.eks_clusters = vec![
+            eks("main", None, None),
+            eks("batch", Some("eu-west-1"), None),
+        ];
+        let profile = Profile::SsoProfile(sso);
+        assert_eq!(profile.eks_cluster(None).unwrap().cluster, "main");
+        assert_eq!(
+            profile
+                .eks_cluster(Some("batch"))
+                .unwrap()
+                .region
+                .as_deref(),
+            Some("eu-west-1")
+        );
+        assert!(profile.eks_cluster(Some("other")).is_none());
+        assert!(Profile::OtherProfile.eks_cluster(None).is_none());
+    }
+
+    #[test]
+    fn test_find_eks_context() {
+        let mut profiles = make_setup_profiles();
+        if let Some(Profile::SsoProfile(p)) = profiles.profiles.get_mut("dev") {
+            p.eks_clusters = vec![eks("main", Some("us-west-2"), Some("dev-main"))];
+        }
+        if let Some(Profile::AssumeSsoProfile(p)) = profiles.profiles.get_mut("assume-prod") {
+            p.eks_clusters = vec![
+                eks("main", None, Some("prod-main")),
+                eks("shared", None, None),
+            ];
+        }
+        let (name, cluster) = profiles.find_eks_context("prod-main").unwrap();
+        assert_eq!(name, "assume-prod");
+        assert_eq!(cluster, eks("main", None, Some("prod-main")));
+        let (name, _) = profiles.find_eks_context("dev-main").unwrap();
+        assert_eq!(name, "dev");
+        assert!(profiles.find_eks_context("main").is_err());
+
+        if let Some(Profile::SsoProfile(p)) = profiles.profiles.get_mut("dev") {
+            p.eks_clusters.push(eks("shared", None, None));
+        }
+        let err = profiles.find_eks_context("shared").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EKS context 'shared' is defined by several profiles: assume-prod, dev"
+        );
+    }
+
+    #[test]
+    fn test_eks_clusters_serde() {
+        let mut sso = make_sso_profile("dev", "https://my-sso.awsapps.com/start");
+        let json = serde_json::to_string(&sso).unwrap();
+        assert!(!json.contains("eks_clusters"));
+        sso.eks_clusters = vec![eks("main", Some("us-west-2"), None)];
+        let json = serde_json::to_string(&sso).unwrap();
+        assert!(json.contains(r#""eks_clusters":[{"cluster":"main","region":"us-west-2"}]"#));
+        let back: SsoProfile = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, sso);
+    }
+
+    #[test]
+    fn test_from_ini_keeps_eks_clusters() {
+        let conf = Ini::load_from_str(NATIVE_CONFIG).unwrap();
+        let mut managed = make_setup_profiles();
+        if let Some(Profile::SsoProfile(p)) = managed.profiles.get_mut("dev") {
+            p.eks_clusters = vec![eks("main", None, None)];
+        }
+        let (profiles, _) = Profiles::from_ini(&conf, &managed).unwrap();
+        assert_eq!(
+            profiles.profiles["dev"].eks_clusters(),
+            &[eks("main", None, None)]
+        );
+        assert!(profiles.profiles["assume-prod"].eks_clusters().is_empty());
+    }
 }
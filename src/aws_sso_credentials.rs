@@ -1,17 +1,47 @@
 use crate::aws_profile::SsoProfile;
 use crate::aws_sso_registration::SsoRegistration;
 use crate::cache::{cache_sso_credentials, get_cached_sso_credentials};
-use crate::config::ProgramConfig;
+use crate::config::{sdk_config, ProgramConfig};
+use crate::error::{is_unreachable, unreachable_hint, with_hint, Error, Hint};
 use crate::mywebbrowser::open_url;
 use anyhow::{anyhow, Result};
 use aws_config::sso::credentials::Builder;
 use aws_sdk_ssooidc;
+use aws_sdk_ssooidc::operation::create_token::CreateTokenError;
+use aws_sdk_ssooidc::operation::start_device_authorization::StartDeviceAuthorizationError;
+use aws_smithy_runtime_api::client::result::SdkError;
 use aws_types::region::Region as sdkRegion;
+use aws_types::sdk_config::SharedCredentialsProvider;
 use chrono::{Duration, Local, NaiveDateTime};
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration as StdDuration, Instant};
 
+// Cleared when the caller cannot interact with a browser, e.g. kubectl
+// running the eks exec plugin with interactive: false.
+static BROWSER_LOGIN: AtomicBool = AtomicBool::new(true);
+
+pub fn disable_browser_login() {
+    BROWSER_LOGIN.store(false, Ordering::SeqCst);
+}
+
+tokio::task_local! {
+    static SCOPED_BROWSER_LOGIN: bool;
+}
+
+// Runs f with browser login allowed or not, whatever the process default.
+pub async fn with_browser_login<F: Future>(allowed: bool, f: F) -> F::Output {
+    SCOPED_BROWSER_LOGIN.scope(allowed, f).await
+}
+
+fn browser_login_allowed() -> bool {
+    SCOPED_BROWSER_LOGIN
+        .try_with(|allowed| *allowed)
+        .unwrap_or_else(|_| BROWSER_LOGIN.load(Ordering::SeqCst))
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[allow(non_snake_case)]
 pub struct SsoCredentials {
@@ -74,13 +104,20 @@ impl SsoCredentials {
             "refresh (calling AWS api) SsoCredentials for {}",
             profile.profile_name
         );
+        if !browser_login_allowed() {
+            error!("aws_sso_credentials.SsoCredentials.refresh browser login disabled");
+            let name = profile.profile_name;
+            return Err(
+                anyhow!(Error::SsoExpired(name.clone())).context(MyErrors::NonInteractive(name))
+            );
+        }
         let conf = ProgramConfig::new()?;
         debug!("getting login url from AWS");
         let url_code = SsoCredentials::login_url_from_aws(profile.clone())
             .await
             .map_err(|e| {
                 error!("aws_sso_credentials.SsoCredentials.refresh {}", e);
-                anyhow!(MyErrors::GetUrlError)
+                e.context(MyErrors::GetUrlError)
             })?;
 
         // AWS returns a device_code with a fixed lifetime (typically ~10 minutes)
@@ -98,7 +135,11 @@ impl SsoCredentials {
         debug!("opening browser to complete SSO authorization");
         open_url(conf, url_as_str.clone()).map_err(|e| {
             error!("aws_sso_credentials.SsoCredentials.refresh open_url: {}", e);
-            anyhow!(MyErrors::GetUrlError)
+            e.context(Hint(
+                "set browser to chrome, firefox or safari in ssologinlite.toml, or set a default browser"
+                    .to_string(),
+            ))
+            .context(MyErrors::GetUrlError)
         })?;
 
         let deadline = Instant::now() + StdDuration::from_secs(expires_in_secs);
@@ -132,35 +173,33 @@ impl SsoCredentials {
                 }
             }
         }
-        Err(anyhow!(
-            "SSO device-code authorization timed out after {} seconds; \
-             the browser flow was not completed in time",
-            expires_in_secs
+        Err(anyhow!(Hint(
+            "approve the request in the browser tab that opened, then run the command again"
+                .to_string()
         ))
+        .context(MyErrors::LoginTimedOut(expires_in_secs)))
     }
 
     pub async fn login_url_from_aws(profile: SsoProfile) -> Result<UrlCode> {
         info!("getting login url from AWS");
-        let sdkregion = sdkRegion::new(profile.sso_region.clone());
         let provider = Builder::new()
-            .region(sdkregion.clone())
+            .region(sdkRegion::new(profile.sso_region.clone()))
             .role_name(&profile.sso_role_name)
             .account_id(&profile.sso_account_id)
             .start_url(profile.sso_start_url.clone())
             .build();
-        let config = aws_sdk_ssooidc::Config::builder()
-            .region(sdkregion)
-            .behavior_version(aws_sdk_ssooidc::config::BehaviorVersion::latest())
-            .credentials_provider(provider)
-            .build();
+        let config = sdk_config(
+            Some(SharedCredentialsProvider::new(provider)),
+            &profile.sso_region,
+        )?;
 
-        let client = aws_sdk_ssooidc::Client::from_conf(config);
+        let client = aws_sdk_ssooidc::Client::new(&config);
         let registration = SsoRegistration::get(&profile.sso_region).await?;
         let output = match client
             .start_device_authorization()
             .set_client_id(Some(registration.clientId.to_owned()))
             .set_client_secret(Some(registration.clientSecret.to_owned()))
-            .set_start_url(Some(profile.sso_start_url))
+            .set_start_url(Some(profile.sso_start_url.clone()))
             .send()
             .await
         {
@@ -170,7 +209,9 @@ impl SsoCredentials {
                     "aws_sso_credentials.SsoCredentials.login_url_from_aws {}",
                     e
                 );
-                return Err(anyhow!(MyErrors::GetRoleCredentialError));
+                let hint = device_authorization_hint(&profile, &e);
+                return Err(with_hint(anyhow!(Error::from_sdk(e)), hint)
+                    .context(MyErrors::GetRoleCredentialError));
             }
         };
 
@@ -201,20 +242,18 @@ impl SsoCredentials {
     pub async fn create_token(profile: SsoProfile, device_code: String) -> Result<SsoCredentials> {
         info!("getting token from AWS");
         let registration = SsoRegistration::get(&profile.sso_region).await?;
-        let sdkregion = sdkRegion::new(profile.sso_region.clone());
         let provider = Builder::new()
-            .region(sdkregion.clone())
+            .region(sdkRegion::new(profile.sso_region.clone()))
             .role_name(&profile.sso_role_name)
             .account_id(&profile.sso_account_id)
             .start_url(&profile.sso_start_url)
             .build();
-        let config = aws_sdk_ssooidc::Config::builder()
-            .region(sdkregion)
-            .behavior_version(aws_sdk_ssooidc::config::BehaviorVersion::latest())
-            .credentials_provider(provider)
-            .build();
+        let config = sdk_config(
+            Some(SharedCredentialsProvider::new(provider)),
+            &profile.sso_region,
+        )?;
 
-        let client = aws_sdk_ssooidc::Client::from_conf(config);
+        let client = aws_sdk_ssooidc::Client::new(&config);
 
         let output = match client
             .create_token()
@@ -235,7 +274,6 @@ impl SsoCredentials {
                 // the caller can keep waiting, and fail fast on everything
                 // else (invalid client/grant, expired device code, access
                 // denied, network errors).
-                use aws_sdk_ssooidc::operation::create_token::CreateTokenError;
                 if matches!(
                     e.as_service_error(),
                     Some(
@@ -250,7 +288,9 @@ impl SsoCredentials {
                     return Err(anyhow!(MyErrors::AuthorizationPending));
                 }
                 error!("aws_sso_credentials.SsoCredentials.create_token {}", e);
-                return Err(anyhow!(MyErrors::GetRoleCredentialError));
+                let hint = create_token_hint(&profile, &e);
+                return Err(with_hint(anyhow!(Error::from_sdk(e)), hint)
+                    .context(MyErrors::GetRoleCredentialError));
             }
         };
         let access_token = match output.access_token {
@@ -302,6 +342,46 @@ impl SsoCredentials {
     }
 }
 
+// What to check when the SSO login could not be started.
+fn device_authorization_hint<R>(
+    profile: &SsoProfile,
+    e: &SdkError<StartDeviceAuthorizationError, R>,
+) -> Option<Hint> {
+    if is_unreachable(e) {
+        return Some(unreachable_hint(&profile.sso_region));
+    }
+    let hint = match e.as_service_error()? {
+        StartDeviceAuthorizationError::InvalidRequestException(_) => format!(
+            "start URL {} was rejected, check sso_start_url and sso_region {}",
+            profile.sso_start_url, profile.sso_region
+        ),
+        StartDeviceAuthorizationError::InvalidClientException(_)
+        | StartDeviceAuthorizationError::UnauthorizedClientException(_) => {
+            "the cached client registration was rejected, delete the ssologinlite cache to register again"
+                .to_string()
+        }
+        _ => return None,
+    };
+    Some(Hint(hint))
+}
+
+// What to check when the approved login could not be turned into a token.
+fn create_token_hint<R>(profile: &SsoProfile, e: &SdkError<CreateTokenError, R>) -> Option<Hint> {
+    if is_unreachable(e) {
+        return Some(unreachable_hint(&profile.sso_region));
+    }
+    let hint = match e.as_service_error()? {
+        CreateTokenError::AccessDeniedException(_) => {
+            "the login was denied in the browser, run the command again to retry".to_string()
+        }
+        CreateTokenError::ExpiredTokenException(_) => {
+            "the login code expired before it was approved, run the command again".to_string()
+        }
+        _ => return None,
+    };
+    Some(Hint(hint))
+}
+
 // Error definitions
 #[derive(Debug)]
 enum MyErrors {
@@ -313,6 +393,8 @@ enum MyErrors {
     // SlowDown) so the refresh() loop can distinguish "keep waiting" from
     // a fatal SDK error and fail fast on the latter.
     AuthorizationPending,
+    NonInteractive(String),
+    LoginTimedOut(u64),
 }
 
 impl std::fmt::Display for MyErrors {
@@ -325,6 +407,16 @@ impl std::fmt::Display for MyErrors {
             Self::AuthorizationPending => {
                 write!(f, "device-code authorization is still pending")
             }
+            Self::NonInteractive(profile) => write!(
+                f,
+                "SSO login needed for profile {} but the caller is not interactive",
+                profile
+            ),
+            Self::LoginTimedOut(seconds) => write!(
+                f,
+                "SSO device-code authorization timed out after {} seconds; the browser flow was not completed in time",
+                seconds
+            ),
         }
     }
 }
@@ -511,4 +603,12 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_non_interactive_error_display() {
+        assert_eq!(
+            MyErrors::NonInteractive("dev".to_string()).to_string(),
+            "SSO login needed for profile dev but the caller is not interactive"
+        );
+    }
 }
@@ -1,10 +1,10 @@
 use crate::{
     cache::{cache_sso_registration, get_cached_sso_registration},
+    config::sdk_config,
     constants::PROGRAM_NAME,
+    error::{is_unreachable, unreachable_hint, with_hint, Error},
 };
 use anyhow::{anyhow, Result};
-use aws_sdk_ssooidc;
-use aws_types::region::Region as sdkRegion;
 use chrono::{DateTime, Utc};
 use log::{debug, error, info};
 use rand::Rng;
@@ -62,17 +62,11 @@ impl SsoRegistration {
     }
 
     pub async fn register_client(sso_region: &str) -> Result<SsoRegistration> {
-        let sdkregion = sdkRegion::new(sso_region.to_string());
+        let config = sdk_config(None, sso_region)?;
+        let client = aws_sdk_ssooidc::Client::new(&config);
 
-        let config = aws_sdk_ssooidc::Config::builder()
-            .region(sdkregion)
-            .behavior_version(aws_sdk_ssooidc::config::BehaviorVersion::latest())
-            .build();
-
-        let client = aws_sdk_ssooidc::Client::from_conf(config);
-
-        let mut rng = rand::thread_rng();
-        let id: u32 = rng.gen_range(100000000..999999999);
+        // The thread rng is not Send, so don't hold it across the await.
+        let id: u32 = rand::thread_rng().gen_range(100000000..999999999);
         let client_name = format!("{}-{}", PROGRAM_NAME, id);
         let output = match client
             .register_client()
@@ -84,7 +78,11 @@ impl SsoRegistration {
             Ok(output) => output,
             Err(e) => {
                 error!("{}", e);
-                return Err(anyhow!(MyErrors::RegisterClientError));
+                // Registration is the first call of a login, so the first to
+                // find out the OIDC endpoint is out of reach.
+                let hint = is_unreachable(&e).then(|| unreachable_hint(sso_region));
+                return Err(with_hint(anyhow!(Error::from_sdk(e)), hint)
+                    .context(MyErrors::RegisterClientError));
             }
         };
         let client_secret = match output.client_secret {
@@ -1,27 +1,21 @@
 use crate::aws_credentials::AWScredentials;
 use crate::aws_sso_credentials::SsoCredentials;
 use crate::aws_sso_registration::SsoRegistration;
-use crate::constants::{CREDS_CACHE, PROGRAM_FOLDER};
-use crate::file_helper::{get_home_os_string, restrict_file_permissions};
+use crate::constants::CREDS_CACHE;
+use crate::error::Error;
+use crate::file_helper::{get_cache_file, restrict_file_permissions, write_file_atomic};
 use anyhow::{anyhow, Result};
 use log::{debug, error, info};
-use pickledb::{PickleDb, PickleDbDumpPolicy, SerializationMethod};
-use serde::Serialize;
-use serde_json;
+use pickledb::{PickleDb, SerializationMethod};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::read_to_string;
 
 // Get cache
 pub async fn get_cached_credentials(profile: &str) -> Option<AWScredentials> {
     let key = format!("{}-creds", profile);
-    match get_cache(key.as_str()).await {
-        Some(cache) => match serde_json::from_str(cache.as_str()) {
-            Ok(res) => Some(res),
-            Err(e) => {
-                error!("{}", e);
-                None
-            }
-        },
-        _ => None,
-    }
+    get_cached(key.as_str()).await
 }
 
 // Store cache
@@ -30,19 +24,52 @@ pub async fn store_cached_credentials(profile: &str, credentials: &AWScredential
     store_cache(key.as_str(), credentials).await
 }
 
+// Credentials of a role assumed from a profile are cached apart from the
+// profile's own, one entry per role and external ID.
+pub fn assumed_role_key(profile: &str, role_arn: &str, external_id: Option<&str>) -> String {
+    match external_id {
+        Some(external_id) => cache_key("assumed-role", &[profile, role_arn, external_id]),
+        None => cache_key("assumed-role", &[profile, role_arn]),
+    }
+}
+
+// A generated EKS token
+pub fn eks_token_key(profile: &str, cluster: &str, region: &str, sts_host: &str) -> String {
+    cache_key("eks-token", &[profile, cluster, region, sts_host])
+}
+
+// Keys built from several names are JSON arrays, as names may contain any
+// separator: joined with `-`, profile a-b and cluster c would share a key
+// with profile a and cluster b-c.
+fn cache_key(kind: &str, names: &[&str]) -> String {
+    let mut key = vec![kind];
+    key.extend_from_slice(names);
+    serde_json::Value::from(key).to_string()
+}
+
+// A minted ECR token
+pub fn ecr_token_key(profile: &str, registry: &str) -> String {
+    cache_key("ecr-token", &[profile, registry])
+}
+
+// A CodeArtifact authorization token. Domain names are only unique within
+// an account and region.
+pub fn codeartifact_token_key(
+    profile: &str,
+    domain: &str,
+    domain_owner: &str,
+    region: &str,
+) -> String {
+    cache_key(
+        "codeartifact-token",
+        &[profile, domain, domain_owner, region],
+    )
+}
+
 // Get sso credentials
 pub async fn get_cached_sso_credentials(url_id: &str) -> Option<SsoCredentials> {
     let key = format!("{}-credentials", url_id);
-    match get_cache(key.as_str()).await {
-        Some(cache) => match serde_json::from_str(cache.as_str()) {
-            Ok(res) => Some(res),
-            Err(e) => {
-                error!("{}", e);
-                None
-            }
-        },
-        _ => None,
-    }
+    get_cached(key.as_str()).await
 }
 
 // Store sso credentials
@@ -53,33 +80,79 @@ pub async fn cache_sso_credentials(url_id: &str, account: &SsoCredentials) -> Re
 
 // Get sso registration
 pub async fn get_cached_sso_registration() -> Option<SsoRegistration> {
-    match get_cache("sso_registration").await {
-        Some(cache) => match serde_json::from_str(cache.as_str()) {
-            Ok(res) => Some(res),
-            Err(e) => {
-                error!("{}", e);
+    get_cached("sso_registration").await
+}
+
+// Store registration
+pub async fn cache_sso_registration(sso_cache: &SsoRegistration) -> Result<()> {
+    store_cache("sso_registration", sso_cache).await
+}
+
+// The cache file is a JSON object of key to cached value, read whole and
+// written back whole through write_file_atomic.
+type CacheMap = HashMap<String, serde_json::Value>;
+
+fn load_cache(cache_file: &OsString) -> Option<CacheMap> {
+    let text = match read_to_string(cache_file) {
+        Ok(text) => text,
+        Err(e) => {
+            debug!("cache.load_cache {}", e);
+            return None;
+        }
+    };
+    match serde_json::from_str(&text) {
+        Ok(cache) => Some(cache),
+        Err(e) => match load_legacy_cache(cache_file) {
+            Some(cache) => {
+                info!("cache.load_cache converting {:?} to JSON", cache_file);
+                if let Err(e) = dump_cache(&cache, cache_file) {
+                    error!("cache.load_cache {}", e);
+                }
+                Some(cache)
+            }
+            None => {
+                error!("cache.load_cache {}", e);
                 None
             }
         },
-        _ => None,
     }
 }
 
-// Store registration
-pub async fn cache_sso_registration(sso_cache: &SsoRegistration) -> Result<()> {
-    store_cache("sso_registration", sso_cache).await
+// Older versions kept the cache in pickledb, each value the JSON text of the
+// cached object. Reading it keeps the SSO registration and token, so the
+// upgrade doesn't force a new browser login.
+fn load_legacy_cache(cache_file: &OsString) -> Option<CacheMap> {
+    let db = match PickleDb::load_read_only(cache_file, SerializationMethod::Bin) {
+        Ok(db) => db,
+        Err(e) => {
+            debug!("cache.load_legacy_cache {}", e);
+            return None;
+        }
+    };
+    let cache = db
+        .get_all()
+        .into_iter()
+        .filter_map(|key| {
+            let value = serde_json::from_str(&db.get::<String>(&key)?).ok()?;
+            Some((key, value))
+        })
+        .collect();
+    Some(cache)
+}
+
+fn dump_cache(cache: &CacheMap, cache_file: &OsString) -> Result<()> {
+    write_file_atomic(cache_file, serde_json::to_string(cache)?.as_bytes())
 }
 
 // Generic get cache
 pub async fn get_cache(key: &str) -> Option<String> {
-    let str_cache_file =
-        match get_home_os_string(format!("{}/{}", PROGRAM_FOLDER, CREDS_CACHE).as_str()) {
-            Ok(rel_cache_file) => rel_cache_file,
-            Err(e) => {
-                error!("{}", e);
-                return None;
-            }
-        };
+    let str_cache_file = match get_cache_file(CREDS_CACHE) {
+        Ok(rel_cache_file) => rel_cache_file,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
 
     // cache
     let cache_str = match str_cache_file.to_str() {
@@ -91,16 +164,25 @@ pub async fn get_cache(key: &str) -> Option<String> {
     };
     debug!("opening cache file {} for reading.", &cache_str);
     debug!("getting {key} from cache.");
-    let db = match PickleDb::load_read_only(&str_cache_file, SerializationMethod::Bin) {
-        Ok(res) => res,
-        Err(e) => {
-            error!("cache.get_cache: {}", e);
-            return None;
-        }
-    };
+    let cache = load_cache(&str_cache_file)?;
 
     let _ = restrict_file_permissions(&str_cache_file);
-    db.get::<String>(key)
+    cache.get(key).map(|value| value.to_string())
+}
+
+// Generic get cache, parsed into T
+pub async fn get_cached<T>(key: &str) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    let cache = get_cache(key).await?;
+    match serde_json::from_str(cache.as_str()) {
+        Ok(res) => Some(res),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
 }
 
 // Generic store cache
@@ -108,14 +190,13 @@ pub async fn store_cache<T>(key: &str, object: &T) -> Result<()>
 where
     T: Serialize,
 {
-    let str_cache_file =
-        match get_home_os_string(format!("{}/{}", PROGRAM_FOLDER, CREDS_CACHE).as_str()) {
-            Ok(rel_cache_file) => rel_cache_file,
-            Err(e) => {
-                error!("{}", e);
-                return Err(anyhow!(MyErrors::Cache));
-            }
-        };
+    let str_cache_file = match get_cache_file(CREDS_CACHE) {
+        Ok(rel_cache_file) => rel_cache_file,
+        Err(e) => {
+            error!("{}", e);
+            return Err(anyhow!(MyErrors::Cache));
+        }
+    };
 
     // cache
     let cache_str = match str_cache_file.to_str() {
@@ -125,31 +206,23 @@ where
         }
     };
     info!("opening cache file {} for writing.", cache_str);
-    let mut db = PickleDb::load(
-        str_cache_file.clone(),
-        PickleDbDumpPolicy::AutoDump,
-        SerializationMethod::Bin,
-    )
-    .unwrap_or(PickleDb::new(
-        str_cache_file.clone(),
-        PickleDbDumpPolicy::AutoDump,
-        SerializationMethod::Bin,
-    ));
-    let _ = restrict_file_permissions(&str_cache_file);
-    let j_creds = match serde_json::to_string(object) {
-        Ok(j_creds) => j_creds,
+    let mut cache = load_cache(&str_cache_file).unwrap_or_default();
+    let value = match serde_json::to_value(object) {
+        Ok(value) => value,
         Err(e) => {
             error!("{}", e);
             return Err(anyhow!(MyErrors::Cache));
         }
     };
-    match db.set(key, &j_creds) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            error!("{}", e);
-            Err(anyhow!(MyErrors::Cache))
-        }
-    }
+    cache.insert(key.to_string(), value);
+    dump_cache(&cache, &str_cache_file).map_err(|e| {
+        error!("cache.store_cache {}", e);
+        let source = Error::CacheCorrupt {
+            file: cache_str.to_string(),
+            source: e.into(),
+        };
+        anyhow!(source).context(MyErrors::Cache)
+    })
 }
 
 // Error definitions
@@ -165,3 +238,179 @@ impl std::fmt::Display for MyErrors {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::ENV_HOME;
+    use crate::eks::Status;
+    use serial_test::serial;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_dump_cache_round_trips() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("cache.db").into_os_string();
+        let mut cache = CacheMap::new();
+        cache.insert("dev-creds".to_string(), serde_json::json!({"Version": 1}));
+        cache.insert("sso_registration".to_string(), serde_json::json!({}));
+        dump_cache(&cache, &path).unwrap();
+
+        assert_eq!(load_cache(&path), Some(cache));
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[tokio::test]
+    async fn test_store_cache_keeps_existing_keys() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let paths = crate::file_helper::PathOverrides {
+            home: Some(tmp.path().to_path_buf()),
+            ..Default::default()
+        };
+        let (a, b) = crate::file_helper::with_paths(paths, async {
+            store_cache("a", &"1").await.unwrap();
+            store_cache("b", &"2").await.unwrap();
+            (get_cache("a").await, get_cache("b").await)
+        })
+        .await;
+        assert_eq!(a, Some("\"1\"".to_string()));
+        assert_eq!(b, Some("\"2\"".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_cache_is_converted() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let cache_file = tmp.path().join(CREDS_CACHE);
+        // What older versions wrote.
+        let mut db = PickleDb::new(
+            &cache_file,
+            pickledb::PickleDbDumpPolicy::AutoDump,
+            SerializationMethod::Bin,
+        );
+        db.set("sso_registration", &r#"{"clientId":"id"}"#.to_string())
+            .unwrap();
+        let paths = crate::file_helper::PathOverrides {
+            home: Some(tmp.path().to_path_buf()),
+            ..Default::default()
+        };
+        let (before, after) = crate::file_helper::with_paths(paths, async {
+            let before = get_cache("sso_registration").await;
+            store_cache("b", &"2").await.unwrap();
+            (before, get_cache("sso_registration").await)
+        })
+        .await;
+        assert_eq!(before, Some(r#"{"clientId":"id"}"#.to_string()));
+        assert_eq!(after, before);
+        let text = std::fs::read_to_string(&cache_file).unwrap();
+        let cache: CacheMap = serde_json::from_str(&text).unwrap();
+        assert_eq!(cache["b"], serde_json::json!("2"));
+    }
+
+    #[tokio::test]
+    #[serial(env_vars)]
+    async fn test_eks_token_cache_is_keyed_by_profile_cluster_region_host() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let saved = std::env::var_os(ENV_HOME);
+        std::env::set_var(ENV_HOME, tmp.path());
+
+        let status = Status {
+            expirationTimestamp: "2099-01-01T00:00:00Z".to_string(),
+            token: "k8s-aws-v1.abc".to_string(),
+        };
+        let host = "sts.eu-west-1.amazonaws.com";
+        let key = eks_token_key("prod", "main", "eu-west-1", host);
+        store_cache(&key, &status).await.unwrap();
+        let cached = get_cached::<Status>(&key).await;
+        let key = eks_token_key("prod", "main", "us-east-1", host);
+        let other_region = get_cached::<Status>(&key).await;
+        let key = eks_token_key("dev", "main", "eu-west-1", host);
+        let other_profile = get_cached::<Status>(&key).await;
+        let key = eks_token_key("prod", "main", "eu-west-1", "sts.amazonaws.com");
+        let other_host = get_cached::<Status>(&key).await;
+
+        match saved {
+            Some(v) => std::env::set_var(ENV_HOME, v),
+            None => std::env::remove_var(ENV_HOME),
+        }
+        assert_eq!(cached, Some(status));
+        assert!(other_region.is_none());
+        assert!(other_profile.is_none());
+        assert!(other_host.is_none());
+    }
+
+    #[test]
+    fn test_eks_token_key_is_unambiguous() {
+        let host = "sts.eu-west-1.amazonaws.com";
+        assert_ne!(
+            eks_token_key("a-b", "c", "eu-west-1", host),
+            eks_token_key("a", "b-c", "eu-west-1", host)
+        );
+        assert_ne!(
+            eks_token_key("a", "b\",\"c", "eu-west-1", host),
+            eks_token_key("a\",\"b", "c", "eu-west-1", host)
+        );
+    }
+
+    #[test]
+    fn test_ecr_token_key_is_unambiguous() {
+        assert_ne!(
+            ecr_token_key("a-b", "c.dkr.ecr.eu-west-1.amazonaws.com"),
+            ecr_token_key("a", "b-c.dkr.ecr.eu-west-1.amazonaws.com")
+        );
+    }
+
+    #[test]
+    fn test_codeartifact_token_key_is_unambiguous() {
+        let key = codeartifact_token_key("dev", "my-domain", "123456789012", "us-west-2");
+        assert_ne!(
+            key,
+            codeartifact_token_key("dev", "my-domain", "123456789012", "eu-west-1")
+        );
+        assert_ne!(
+            codeartifact_token_key("dev-my", "domain", "", "us-west-2"),
+            codeartifact_token_key("dev", "my-domain", "", "us-west-2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unwritable_cache_is_cache_corrupt() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        // A directory where the cache file should be can't be replaced.
+        std::fs::create_dir(tmp.path().join(CREDS_CACHE)).unwrap();
+        let paths = crate::file_helper::PathOverrides {
+            home: Some(tmp.path().to_path_buf()),
+            ..Default::default()
+        };
+        let e = crate::file_helper::with_paths(paths, store_cache("key", &"value"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            crate::error::exit_code(&e),
+            crate::error::EXIT_CACHE_CORRUPT
+        );
+    }
+
+    #[test]
+    fn test_assumed_role_key_includes_role_and_external_id() {
+        let role = "arn:aws:iam::123456789012:role/eks-admin";
+        let plain = assumed_role_key("prod", role, None);
+        let with_id = assumed_role_key("prod", role, Some("ext-1"));
+        assert_ne!(plain, "prod");
+        assert_ne!(plain, with_id);
+        assert_ne!(with_id, assumed_role_key("prod", role, Some("ext-2")));
+        assert_ne!(plain, assumed_role_key("dev", role, None));
+        assert_ne!(
+            plain,
+            assumed_role_key("prod", "arn:aws:iam::123456789012:role/other", None)
+        );
+        assert_ne!(
+            assumed_role_key("a-b", "c", None),
+            assumed_role_key("a", "b-c", None)
+        );
+        assert_ne!(
+            assumed_role_key("prod", "role-x", None),
+            assumed_role_key("prod", "role", Some("x"))
+        );
+    }
+}
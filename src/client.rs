@@ -0,0 +1,246 @@
+use crate::aws_credentials::AWScredentials;
+use crate::aws_profile::{Profile, Profiles};
+use crate::aws_sso_credentials::with_browser_login;
+use crate::constants::PROGRAM_NAME;
+use crate::error::Error;
+use crate::file_helper::{with_paths, with_paths_sync, PathOverrides};
+use aws_credential_types::provider::error::CredentialsError;
+use aws_credential_types::provider::{future, ProvideCredentials};
+use aws_credential_types::Credentials;
+use log::{debug, error};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// The entry point for programs embedding ssologinlite. A Client reads the
+// same files as the CLI unless given explicit paths, and by default never
+// opens a browser: an expired SSO session is an SsoExpired error.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Client {
+    paths: PathOverrides,
+    interactive: bool,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client::default()
+    }
+
+    // The folder holding profiles.json, the cache and the logs, in place of
+    // SSOLOGINLITE_HOME and the XDG directories.
+    pub fn with_home(mut self, home: impl Into<PathBuf>) -> Self {
+        self.paths.home = Some(home.into());
+        self
+    }
+
+    pub fn with_aws_config(mut self, file: impl Into<PathBuf>) -> Self {
+        self.paths.aws_config = Some(file.into());
+        self
+    }
+
+    pub fn with_aws_credentials(mut self, file: impl Into<PathBuf>) -> Self {
+        self.paths.aws_credentials = Some(file.into());
+        self
+    }
+
+    // The ssologinlite.toml to read, e.g. for the browser to log in with.
+    pub fn with_program_config(mut self, file: impl Into<PathBuf>) -> Self {
+        self.paths.program_config = Some(file.into());
+        self
+    }
+
+    // Allow SSO logins in the browser when the session has expired, for
+    // programs run by a person.
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    fn profiles(&self) -> Result<Profiles, Error> {
+        with_paths_sync(self.paths.clone(), Profiles::from_file).map_err(|e| {
+            error!("client.Client.profiles {}", e);
+            Error::from_anyhow(e)
+        })
+    }
+
+    // The names of the profiles ssologinlite manages, sorted.
+    pub fn profile_names(&self) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = self.profiles()?.profiles.into_keys().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn profile(&self, name: &str) -> Result<Profile, Error> {
+        match self.profiles()?.profiles.remove(name) {
+            Some(Profile::OtherProfile) | None => Err(Error::ProfileNotFound(name.to_string())),
+            Some(profile) => Ok(profile),
+        }
+    }
+
+    // The profile's credentials, from the cache while they are valid.
+    pub async fn credentials(&self, name: &str) -> Result<AWScredentials, Error> {
+        let profile = self.profile(name)?;
+        debug!("client.Client.credentials {}", name);
+        // Boxed: the SSO login future is too deep for the compiler to lay
+        // out inline in a ProvideCredentials future.
+        let credentials = Box::pin(with_paths(
+            self.paths.clone(),
+            with_browser_login(self.interactive, profile.get_credentials()),
+        ))
+        .await;
+        credentials.map_err(|e| {
+            error!("client.Client.credentials {} {:#}", name, e);
+            // Keeps what went wrong, e.g. SsoExpired, in place of the context
+            // added on the way up.
+            Error::from_anyhow(e.context(format!("Could not get credentials for profile {}", name)))
+        })
+    }
+
+    // An AWS SDK credentials provider for the profile.
+    pub fn credentials_provider(&self, name: &str) -> CredentialResolver {
+        CredentialResolver {
+            client: self.clone(),
+            profile: name.to_string(),
+        }
+    }
+}
+
+// Resolves one profile's credentials. As a ProvideCredentials it plugs into
+// aws_config::ConfigLoader::credentials_provider; the SDK caches what it
+// returns until the expiration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialResolver {
+    client: Client,
+    profile: String,
+}
+
+impl CredentialResolver {
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    pub async fn resolve(&self) -> Result<Credentials, Error> {
+        let credentials = self.client.credentials(&self.profile).await?;
+        Ok(Credentials::new(
+            &credentials.AccessKeyId,
+            &credentials.SecretAccessKey,
+            Some(credentials.SessionToken.clone()),
+            credentials.expiration().map(SystemTime::from),
+            PROGRAM_NAME,
+        ))
+    }
+}
+
+impl ProvideCredentials for CredentialResolver {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            self.resolve().await.map_err(|e| match e {
+                // Lets a provider chain move on to its next provider.
+                Error::ConfigParse { .. } | Error::ProfileNotFound(_) => {
+                    CredentialsError::not_loaded(e)
+                }
+                _ => CredentialsError::provider_error(e),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_profile::SsoProfile;
+    use crate::cache::store_cached_credentials;
+    use chrono::{Duration, Utc};
+    use std::collections::HashMap;
+
+    fn make_home() -> tempfile::TempDir {
+        let home = tempfile::TempDir::new().unwrap();
+        let profile = |name: &str| {
+            Profile::SsoProfile(SsoProfile {
+                profile_name: name.to_string(),
+                sso_start_url: "https://my-sso.awsapps.com/start".to_string(),
+                sso_region: "us-east-1".to_string(),
+                sso_account_id: "123456789012".to_string(),
+                sso_role_name: "Admin".to_string(),
+                region: Some("eu-west-1".to_string()),
+                ..Default::default()
+            })
+        };
+        let profiles = Profiles {
+            profiles: HashMap::from([
+                ("prod".to_string(), profile("prod")),
+                ("dev".to_string(), profile("dev")),
+            ]),
+        };
+        std::fs::write(
+            home.path().join("profiles.json"),
+            profiles.to_json().unwrap(),
+        )
+        .unwrap();
+        home
+    }
+
+    #[test]
+    fn test_profiles_from_explicit_home() {
+        let home = make_home();
+        let client = Client::new().with_home(home.path());
+        assert_eq!(client.profile_names().unwrap(), vec!["dev", "prod"]);
+        assert_eq!(
+            client.profile("prod").unwrap().region().as_deref(),
+            Some("eu-west-1")
+        );
+        assert!(matches!(
+            client.profile("staging"),
+            Err(Error::ProfileNotFound(name)) if name == "staging"
+        ));
+    }
+
+    #[test]
+    fn test_missing_profiles_file_is_config_error() {
+        let home = tempfile::TempDir::new().unwrap();
+        let client = Client::new().with_home(home.path());
+        let e = client.profile_names().unwrap_err();
+        assert!(matches!(e, Error::ConfigParse { .. }));
+        assert!(std::error::Error::source(&e).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_provider_returns_cached_credentials() {
+        let home = make_home();
+        let client = Client::new().with_home(home.path());
+        let expiration = Utc::now() + Duration::hours(1);
+        let cached = AWScredentials::new("AKIDEXAMPLE", "secret", "token", expiration);
+        with_paths(
+            PathOverrides {
+                home: Some(home.path().to_path_buf()),
+                ..Default::default()
+            },
+            store_cached_credentials("prod", &cached),
+        )
+        .await
+        .unwrap();
+
+        let provider = client.credentials_provider("prod");
+        let credentials = provider.provide_credentials().await.unwrap();
+        assert_eq!(credentials.access_key_id(), "AKIDEXAMPLE");
+        assert_eq!(credentials.session_token(), Some("token"));
+        assert_eq!(
+            credentials.expiry(),
+            Some(SystemTime::from(cached.expiration().unwrap()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_unknown_profile_is_not_loaded() {
+        let home = make_home();
+        let provider = Client::new()
+            .with_home(home.path())
+            .credentials_provider("staging");
+        assert!(matches!(
+            provider.provide_credentials().await,
+            Err(CredentialsError::CredentialsNotLoaded(_))
+        ));
+    }
+}
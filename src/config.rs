@@ -1,19 +1,49 @@
-use crate::constants::{CONFIG_FILE, PROGRAM_NAME};
-use crate::file_helper::get_home_os_string;
+use crate::constants::PROGRAM_NAME;
+use crate::error::Error;
+use crate::file_helper::get_program_config;
+use crate::http_client::sdk_http_client;
 use anyhow::{anyhow, Result};
+use aws_config::BehaviorVersion;
+use aws_smithy_types::retry::RetryConfig;
+use aws_smithy_types::timeout::TimeoutConfig;
+use aws_types::region::Region as sdkRegion;
+use aws_types::sdk_config::SharedCredentialsProvider;
+use aws_types::SdkConfig;
 use config::Config;
 use log::error;
 use serde::{Deserialize, Serialize};
-// use std::{collections::HashMap, ffi::OsString};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Defaults for the AWS calls. The retries keep the SDK's standard 3
+// attempts; the read timeout is one the SDK doesn't set.
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_READ_TIMEOUT: u64 = 30;
+const DEFAULT_MAX_RETRIES: u32 = 2;
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct ProgramConfig {
     pub browser: Option<String>,
     pub default_sso_url: Option<String>,
+    // ECR registry host -> profile, for the docker credential helper.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ecr_registries: HashMap<String, String>,
+    // Seconds to wait for a connection to AWS, and for each read once
+    // connected.
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    // Retries after the first attempt, for throttling, 5xx and connection
+    // errors; 0 disables them.
+    pub max_retries: Option<u32>,
+    // An http:// proxy for AWS calls, hosts to reach without it and extra
+    // root certificates; HTTPS_PROXY, NO_PROXY and AWS_CA_BUNDLE otherwise.
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub ca_bundle: Option<String>,
 }
 impl ProgramConfig {
     pub fn new() -> Result<Self> {
-        let config_file = get_home_os_string(CONFIG_FILE)?;
+        let config_file = get_program_config()?;
         let cf_str = match config_file.to_str() {
             Some(s) => s,
             None => {
@@ -29,21 +59,71 @@ impl ProgramConfig {
             // Add in settings from the environment (with a prefix of APP)
             // Eg.. `APP_DEBUG=1 ./target/app` would set the `debug` key
             .add_source(config::Environment::with_prefix(env_prefix))
-            .build()?;
-        match settings.try_deserialize() {
+            .build()
+            .and_then(|settings| settings.try_deserialize());
+        match settings {
             Ok(settings) => Ok(settings),
             Err(e) => {
                 error!("config.get_conf: try_deserialize failed: {:?}", e);
-                Err(anyhow!("config.get_conf: try_deserialize failed: {:?}", e))
+                Err(anyhow!(Error::ConfigParse {
+                    file: cf_str.to_string(),
+                    source: e.into(),
+                }))
             }
         }
     }
+
+    // For the timeout_config of every AWS SDK client.
+    pub fn timeout_config(&self) -> TimeoutConfig {
+        TimeoutConfig::builder()
+            .connect_timeout(Duration::from_secs(
+                self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+            ))
+            .read_timeout(Duration::from_secs(
+                self.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT),
+            ))
+            .build()
+    }
+
+    // For the retry_config of every AWS SDK client. Standard mode backs off
+    // exponentially with full jitter, and counts throttling such as SSO's
+    // TooManyRequestsException as retryable.
+    pub fn retry_config(&self) -> RetryConfig {
+        let max_retries = self.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+        RetryConfig::standard().with_max_attempts(max_retries.saturating_add(1))
+    }
+}
+
+// The config every AWS SDK client is built from, so the timeouts, retries
+// and proxy settings are read once per client in one place.
+pub fn sdk_config(
+    credentials: Option<SharedCredentialsProvider>,
+    region: &str,
+) -> Result<SdkConfig> {
+    let conf = ProgramConfig::new()?;
+    let mut builder = SdkConfig::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(sdkRegion::new(region.to_string()))
+        .timeout_config(conf.timeout_config())
+        .retry_config(conf.retry_config())
+        .http_client(sdk_http_client(&conf)?);
+    builder.set_credentials_provider(credentials);
+    Ok(builder.build())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_helper::tests::with_temp_env;
     use serial_test::serial;
+    use std::path::PathBuf;
+
+    // Writes the program config, as TOML, where ProgramConfig::new looks.
+    fn write_config(contents: &str) {
+        let path = PathBuf::from(get_program_config().unwrap()).with_extension("toml");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
 
     // Tests that read or mutate process-global env vars share a serialization
     // group via #[serial] — Rust runs tests in parallel by default, so a sibling
@@ -86,6 +166,7 @@ mod tests {
         let conf = ProgramConfig {
             browser: Some("chrome".to_string()),
             default_sso_url: Some("https://url".to_string()),
+            ..Default::default()
         };
         let json = serde_json::to_string(&conf).unwrap();
         let deser: ProgramConfig = serde_json::from_str(&json).unwrap();
@@ -98,6 +179,7 @@ mod tests {
         let conf = ProgramConfig {
             browser: None,
             default_sso_url: None,
+            ..Default::default()
         };
         let json = serde_json::to_string(&conf).unwrap();
         let deser: ProgramConfig = serde_json::from_str(&json).unwrap();
@@ -117,9 +199,67 @@ mod tests {
         let conf = ProgramConfig {
             browser: Some("safari".to_string()),
             default_sso_url: None,
+            ..Default::default()
         };
         let cloned = conf.clone();
         assert_eq!(cloned.browser, conf.browser);
         assert_eq!(cloned.default_sso_url, conf.default_sso_url);
     }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_ecr_registries_from_file() {
+        with_temp_env(&[], |_| {
+            write_config(
+                "[ecr_registries]\n\"123456789012.dkr.ecr.us-west-2.amazonaws.com\" = \"Prod\"\n",
+            );
+            let conf = ProgramConfig::new().unwrap();
+            assert_eq!(
+                conf.ecr_registries
+                    .get("123456789012.dkr.ecr.us-west-2.amazonaws.com")
+                    .map(String::as_str),
+                Some("Prod")
+            );
+        });
+    }
+
+    #[test]
+    fn test_sdk_defaults() {
+        let conf = ProgramConfig::default();
+        let timeouts = conf.timeout_config();
+        assert_eq!(timeouts.connect_timeout(), Some(Duration::from_secs(10)));
+        assert_eq!(timeouts.read_timeout(), Some(Duration::from_secs(30)));
+        // The SDK's standard mode makes 3 attempts.
+        assert_eq!(conf.retry_config().max_attempts(), 3);
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_sdk_settings_from_file_and_env() {
+        with_temp_env(&[("SSOLOGINLITE_MAX_RETRIES", "0")], |_| {
+            write_config("connect_timeout = 3\nread_timeout = 20\n");
+            let conf = ProgramConfig::new().unwrap();
+            let timeouts = conf.timeout_config();
+            assert_eq!(timeouts.connect_timeout(), Some(Duration::from_secs(3)));
+            assert_eq!(timeouts.read_timeout(), Some(Duration::from_secs(20)));
+            // No retries is a single attempt.
+            assert_eq!(conf.retry_config().max_attempts(), 1);
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_sdk_config_carries_settings() {
+        with_temp_env(&[("SSOLOGINLITE_READ_TIMEOUT", "5")], |_| {
+            let config = sdk_config(None, "eu-west-1").unwrap();
+            assert_eq!(config.region().map(|r| r.as_ref()), Some("eu-west-1"));
+            assert_eq!(
+                config.timeout_config().and_then(|t| t.read_timeout()),
+                Some(Duration::from_secs(5))
+            );
+            assert_eq!(config.retry_config().map(|r| r.max_attempts()), Some(3));
+            assert!(config.credentials_provider().is_none());
+            assert!(config.http_client().is_some());
+        });
+    }
 }
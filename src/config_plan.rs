@@ -0,0 +1,343 @@
+use crate::file_helper::{get_aws_config, write_file_atomic};
+use anyhow::{anyhow, Result};
+use ini::Ini;
+use log::{debug, error, info};
+use similar::TextDiff;
+use std::ffi::OsString;
+use std::fs::read_to_string;
+
+// A single INI edit, as computed by ConfigPlan::changes().
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigChange {
+    AddSection {
+        section: String,
+    },
+    RemoveSection {
+        section: String,
+    },
+    SetKey {
+        section: String,
+        key: String,
+        old: Option<String>,
+        new: String,
+    },
+    RemoveKey {
+        section: String,
+        key: String,
+        old: String,
+    },
+}
+
+impl std::fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AddSection { section } => write!(f, "add [{section}]"),
+            Self::RemoveSection { section } => write!(f, "remove [{section}]"),
+            Self::SetKey {
+                section,
+                key,
+                old: None,
+                new,
+            } => write!(f, "[{section}] set {key} = {new}"),
+            Self::SetKey {
+                section,
+                key,
+                old: Some(old),
+                new,
+            } => write!(f, "[{section}] change {key}: {old} -> {new}"),
+            Self::RemoveKey { section, key, .. } => write!(f, "[{section}] remove {key}"),
+        }
+    }
+}
+
+// The intended edits to an INI file, computed in memory before anything is
+// written. The diff is taken from the file as it is on disk, so comments and
+// formatting that rust-ini drops on write show up as removed lines.
+#[derive(Debug, Clone)]
+pub struct ConfigPlan {
+    pub path: OsString,
+    before_text: String,
+    before: Ini,
+    after: Ini,
+}
+
+impl ConfigPlan {
+    pub fn load(path: OsString) -> Result<ConfigPlan> {
+        debug!("config_plan.ConfigPlan.load {:?}", path);
+        let before_text = match read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                error!("config_plan.ConfigPlan.load {}", e);
+                return Err(anyhow!(MyErrors::Read));
+            }
+        };
+        let before = match Ini::load_from_str(&before_text) {
+            Ok(conf) => conf,
+            Err(e) => {
+                error!("config_plan.ConfigPlan.load {}", e);
+                return Err(anyhow!(MyErrors::Parse));
+            }
+        };
+        Ok(ConfigPlan {
+            path,
+            before_text,
+            after: before.clone(),
+            before,
+        })
+    }
+
+    pub fn load_aws_config() -> Result<ConfigPlan> {
+        ConfigPlan::load(get_aws_config()?)
+    }
+
+    pub fn edit<F>(mut self, f: F) -> Result<ConfigPlan>
+    where
+        F: FnOnce(&mut Ini) -> Result<()>,
+    {
+        f(&mut self.after)?;
+        Ok(self)
+    }
+
+    pub fn changes(&self) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+        for (name, after) in self.after.iter() {
+            let Some(name) = name else { continue };
+            let before = self.before.section(Some(name));
+            if before.is_none() {
+                changes.push(ConfigChange::AddSection {
+                    section: name.to_string(),
+                });
+            }
+            for (key, new) in after.iter() {
+                let old = before.and_then(|b| b.get(key));
+                if old != Some(new) {
+                    changes.push(ConfigChange::SetKey {
+                        section: name.to_string(),
+                        key: key.to_string(),
+                        old: old.map(str::to_string),
+                        new: new.to_string(),
+                    });
+                }
+            }
+            if let Some(before) = before {
+                for (key, old) in before.iter() {
+                    if !after.contains_key(key) {
+                        changes.push(ConfigChange::RemoveKey {
+                            section: name.to_string(),
+                            key: key.to_string(),
+                            old: old.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        for (name, _) in self.before.iter() {
+            let Some(name) = name else { continue };
+            if self.after.section(Some(name)).is_none() {
+                changes.push(ConfigChange::RemoveSection {
+                    section: name.to_string(),
+                });
+            }
+        }
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes().is_empty()
+    }
+
+    // Unified diff between the file as loaded and what apply() would write.
+    pub fn diff(&self) -> Result<String> {
+        let after_text = render(&self.after)?;
+        let path = self.path.to_string_lossy();
+        Ok(TextDiff::from_lines(&self.before_text, &after_text)
+            .unified_diff()
+            .context_radius(3)
+            .header(&path, &path)
+            .to_string())
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        info!("Writing {:?}", self.path);
+        write_file_atomic(&self.path, render(&self.after)?.as_bytes())
+    }
+}
+
+fn render(conf: &Ini) -> Result<String> {
+    let mut buf = Vec::new();
+    conf.write_to(&mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
+
+// Error definitions
+#[derive(Debug)]
+enum MyErrors {
+    Read,
+    Parse,
+}
+
+impl std::fmt::Display for MyErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "Could not read config file!"),
+            Self::Parse => write!(f, "Could not parse config file!"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "[profile dev]
+sso_region = us-west-2
+region = us-west-2
+
+[profile old]
+region = eu-west-1
+";
+
+    fn write_config(text: &str) -> (tempfile::TempDir, OsString) {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("config");
+        std::fs::write(&path, text).unwrap();
+        (tmp, path.into_os_string())
+    }
+
+    fn edited_plan() -> (tempfile::TempDir, ConfigPlan) {
+        let (tmp, path) = write_config(CONFIG);
+        let plan = ConfigPlan::load(path)
+            .unwrap()
+            .edit(|conf| {
+                let dev = conf.section_mut(Some("profile dev")).unwrap();
+                dev.remove("sso_region");
+                dev.insert("region", "us-east-1");
+                dev.insert("credential_process", "ssologinlite token --profile dev");
+                conf.delete(Some("profile old"));
+                conf.with_section(Some("profile new"))
+                    .set("region", "eu-west-1");
+                Ok(())
+            })
+            .unwrap();
+        (tmp, plan)
+    }
+
+    #[test]
+    fn test_load_missing_file_is_empty() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let plan = ConfigPlan::load(tmp.path().join("config").into_os_string()).unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.diff().unwrap(), "");
+    }
+
+    #[test]
+    fn test_unedited_plan_is_empty() {
+        let (_tmp, path) = write_config(CONFIG);
+        let plan = ConfigPlan::load(path).unwrap();
+        assert!(plan.changes().is_empty());
+    }
+
+    #[test]
+    fn test_changes() {
+        let (_tmp, plan) = edited_plan();
+        let changes = plan.changes();
+        assert!(changes.contains(&ConfigChange::SetKey {
+            section: "profile dev".to_string(),
+            key: "region".to_string(),
+            old: Some("us-west-2".to_string()),
+            new: "us-east-1".to_string(),
+        }));
+        assert!(changes.contains(&ConfigChange::SetKey {
+            section: "profile dev".to_string(),
+            key: "credential_process".to_string(),
+            old: None,
+            new: "ssologinlite token --profile dev".to_string(),
+        }));
+        assert!(changes.contains(&ConfigChange::RemoveKey {
+            section: "profile dev".to_string(),
+            key: "sso_region".to_string(),
+            old: "us-west-2".to_string(),
+        }));
+        assert!(changes.contains(&ConfigChange::RemoveSection {
+            section: "profile old".to_string(),
+        }));
+        assert!(changes.contains(&ConfigChange::AddSection {
+            section: "profile new".to_string(),
+        }));
+        assert_eq!(changes.len(), 6);
+    }
+
+    #[test]
+    fn test_diff_is_unified() {
+        let (_tmp, plan) = edited_plan();
+        let diff = plan.diff().unwrap();
+        assert!(diff.starts_with("--- "));
+        assert!(diff.contains("+++ "));
+        assert!(diff.contains("-sso_region = us-west-2"));
+        assert!(!diff.contains("-[profile dev]"));
+        assert!(diff.contains("+credential_process="));
+        assert!(diff.contains("+[profile new]"));
+    }
+
+    #[test]
+    fn test_diff_shows_dropped_comments() {
+        let (_tmp, path) = write_config(&format!("# managed by dotfiles\n{}", CONFIG));
+        let plan = ConfigPlan::load(path)
+            .unwrap()
+            .edit(|conf| {
+                conf.with_section(Some("profile new"))
+                    .set("region", "eu-west-1");
+                Ok(())
+            })
+            .unwrap();
+        let diff = plan.diff().unwrap();
+        assert!(diff.contains("-# managed by dotfiles"));
+        plan.apply().unwrap();
+        // The diff is against the bytes apply() wrote, not a re-render.
+        let written = read_to_string(&plan.path).unwrap();
+        assert_eq!(diff, {
+            let path = plan.path.to_string_lossy();
+            TextDiff::from_lines(&plan.before_text, &written)
+                .unified_diff()
+                .context_radius(3)
+                .header(&path, &path)
+                .to_string()
+        });
+    }
+
+    #[test]
+    fn test_edit_does_not_touch_disk() {
+        let (_tmp, plan) = edited_plan();
+        assert_eq!(read_to_string(&plan.path).unwrap(), CONFIG);
+    }
+
+    #[test]
+    fn test_apply_writes_after() {
+        let (_tmp, plan) = edited_plan();
+        plan.apply().unwrap();
+        let written = Ini::load_from_file(&plan.path).unwrap();
+        assert!(written.section(Some("profile old")).is_none());
+        assert_eq!(
+            written.section(Some("profile new")).unwrap().get("region"),
+            Some("eu-west-1")
+        );
+        assert!(ConfigPlan::load(plan.path.clone()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_load_invalid_errors() {
+        let (_tmp, path) = write_config("[unterminated\nkey = value\n");
+        assert!(ConfigPlan::load(path).is_err());
+    }
+
+    #[test]
+    fn test_change_display() {
+        let change = ConfigChange::RemoveKey {
+            section: "profile dev".to_string(),
+            key: "sso_region".to_string(),
+            old: "us-west-2".to_string(),
+        };
+        assert_eq!(format!("{}", change), "[profile dev] remove sso_region");
+    }
+}
@@ -2,8 +2,26 @@ pub const CREDS_CACHE: &str = ".ssologinlite_cache";
 pub const PROGRAM_FOLDER: &str = ".aws/ssologinlite";
 pub const PROGRAM_NAME: &str = "ssologinlite";
 pub const PROFILES: &str = "profiles.json";
+pub const SYNC_BASE: &str = "sync_base.json";
 pub const CONFIG_FILE: &str = ".config/ssologinlite";
 pub const AWS_CONFIG: &str = ".aws/config";
+pub const AWS_CREDENTIALS: &str = ".aws/credentials";
+pub const ENV_HOME: &str = "SSOLOGINLITE_HOME";
+pub const ENV_AWS_CONFIG_FILE: &str = "AWS_CONFIG_FILE";
+pub const ENV_AWS_CREDENTIALS_FILE: &str = "AWS_SHARED_CREDENTIALS_FILE";
+pub const KUBECONFIG: &str = ".kube/config";
+pub const ENV_KUBECONFIG: &str = "KUBECONFIG";
+pub const ENV_KUBERNETES_EXEC_INFO: &str = "KUBERNETES_EXEC_INFO";
+pub const DOCKER_CREDENTIAL_HELPER: &str = "docker-credential-ssologinlite";
+pub const NPMRC: &str = ".npmrc";
+pub const ENV_NPMRC: &str = "NPM_CONFIG_USERCONFIG";
+pub const PIP_CONF: &str = ".config/pip/pip.conf";
+pub const ENV_PIP_CONF: &str = "PIP_CONFIG_FILE";
+pub const CARGO_HOME: &str = ".cargo";
+pub const ENV_CARGO_HOME: &str = "CARGO_HOME";
+pub const ENV_HTTPS_PROXY: &str = "HTTPS_PROXY";
+pub const ENV_NO_PROXY: &str = "NO_PROXY";
+pub const ENV_AWS_CA_BUNDLE: &str = "AWS_CA_BUNDLE";
 
 #[cfg(test)]
 mod tests {
@@ -29,6 +47,11 @@ mod tests {
         assert_eq!(PROFILES, "profiles.json");
     }
 
+    #[test]
+    fn test_sync_base() {
+        assert_eq!(SYNC_BASE, "sync_base.json");
+    }
+
     #[test]
     fn test_config_file() {
         assert_eq!(CONFIG_FILE, ".config/ssologinlite");
@@ -38,4 +61,31 @@ mod tests {
     fn test_aws_config() {
         assert_eq!(AWS_CONFIG, ".aws/config");
     }
+
+    #[test]
+    fn test_aws_credentials() {
+        assert_eq!(AWS_CREDENTIALS, ".aws/credentials");
+    }
+
+    #[test]
+    fn test_env_vars() {
+        assert_eq!(ENV_HOME, "SSOLOGINLITE_HOME");
+        assert_eq!(ENV_AWS_CONFIG_FILE, "AWS_CONFIG_FILE");
+        assert_eq!(ENV_AWS_CREDENTIALS_FILE, "AWS_SHARED_CREDENTIALS_FILE");
+        assert_eq!(ENV_KUBECONFIG, "KUBECONFIG");
+        assert_eq!(ENV_KUBERNETES_EXEC_INFO, "KUBERNETES_EXEC_INFO");
+        assert_eq!(ENV_NPMRC, "NPM_CONFIG_USERCONFIG");
+        assert_eq!(ENV_PIP_CONF, "PIP_CONFIG_FILE");
+        assert_eq!(ENV_CARGO_HOME, "CARGO_HOME");
+    }
+
+    #[test]
+    fn test_kubeconfig() {
+        assert_eq!(KUBECONFIG, ".kube/config");
+    }
+
+    #[test]
+    fn test_docker_credential_helper() {
+        assert_eq!(DOCKER_CREDENTIAL_HELPER, "docker-credential-ssologinlite");
+    }
 }
@@ -0,0 +1,333 @@
+use crate::aws_credentials::AWScredentials;
+use crate::aws_profile::{Profile, Profiles};
+use crate::cache::{ecr_token_key, get_cached, store_cache};
+use crate::config::{sdk_config, ProgramConfig};
+use crate::error::Error;
+use anyhow::{anyhow, Result};
+use aws_smithy_types_convert::date_time::DateTimeExt;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// What docker expects on stdout when a helper has nothing for a registry.
+pub const CREDENTIALS_NOT_FOUND: &str = "credentials not found in native keychain";
+// A cached token is only handed out while it has this long left.
+const TOKEN_REFRESH_MARGIN_MINS: i64 = 5;
+
+// A private ECR registry, <account>.dkr.ecr[-fips].<region>.amazonaws.com[.cn].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EcrRegistry {
+    pub host: String,
+    pub account: String,
+    pub region: String,
+}
+
+impl EcrRegistry {
+    pub fn from_server_url(server_url: &str) -> Result<EcrRegistry> {
+        let host = registry_host(server_url);
+        let parts: Vec<&str> = host.split('.').collect();
+        match parts.as_slice() {
+            [account, "dkr", "ecr" | "ecr-fips", region, "amazonaws", "com", ..]
+                if account.len() == 12 && account.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Ok(EcrRegistry {
+                    host: host.clone(),
+                    account: account.to_string(),
+                    region: region.to_string(),
+                })
+            }
+            _ => Err(anyhow!(MyErrors::NotAnEcrRegistry(host.clone()))),
+        }
+    }
+}
+
+// The lowercased host of a docker server URL, without scheme or path.
+pub fn registry_host(server_url: &str) -> String {
+    let url = server_url.trim();
+    let url = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    url.split('/').next().unwrap_or(url).to_lowercase()
+}
+
+// The docker credential helper protocol's credentials document.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[allow(non_snake_case)]
+pub struct DockerCredentials {
+    pub ServerURL: String,
+    pub Username: String,
+    pub Secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EcrToken {
+    pub username: String,
+    pub secret: String,
+    pub expires_at: String,
+}
+
+impl EcrToken {
+    pub fn is_fresh(&self) -> bool {
+        match DateTime::parse_from_rfc3339(&self.expires_at) {
+            Ok(expires_at) => {
+                expires_at.with_timezone(&Utc) - Utc::now()
+                    > Duration::minutes(TOKEN_REFRESH_MARGIN_MINS)
+            }
+            Err(_) => false,
+        }
+    }
+
+    // The cached token of (profile, registry) while it is fresh, else a new
+    // one minted with the profile's credentials.
+    pub async fn get(
+        profile: &Profile,
+        profile_name: &str,
+        registry: &EcrRegistry,
+    ) -> Result<EcrToken> {
+        let key = ecr_token_key(profile_name, &registry.host);
+        if let Some(token) = get_cached::<EcrToken>(&key)
+            .await
+            .filter(|token| token.is_fresh())
+        {
+            debug!(
+                "ecr.EcrToken.get cached token for {} still valid",
+                registry.host
+            );
+            return Ok(token);
+        }
+        let credentials = profile.get_credentials().await?;
+        let token = EcrToken::from_aws(&credentials, &registry.region).await?;
+        if let Err(e) = store_cache(&key, &token).await {
+            error!("ecr.EcrToken.get {}", e);
+        }
+        Ok(token)
+    }
+
+    async fn from_aws(credentials: &AWScredentials, region: &str) -> Result<EcrToken> {
+        info!("Getting ECR authorization token from AWS");
+        let output = match ecr_client(credentials, region)?
+            .get_authorization_token()
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("ecr.EcrToken.from_aws {}", e);
+                return Err(anyhow!(Error::from_sdk(e)).context(MyErrors::GetAuthorizationToken));
+            }
+        };
+        let data = match output.authorization_data().first() {
+            Some(data) => data,
+            None => {
+                error!("ecr.EcrToken.from_aws authorization_data is empty");
+                return Err(anyhow!(MyErrors::GetAuthorizationToken));
+            }
+        };
+        let expires_at = match data.expires_at().map(|e| e.to_chrono_utc()) {
+            Some(Ok(expires_at)) => expires_at.to_rfc3339(),
+            _ => {
+                error!("ecr.EcrToken.from_aws expires_at is missing");
+                return Err(anyhow!(MyErrors::GetAuthorizationToken));
+            }
+        };
+        let (username, secret) = decode_authorization_token(data.authorization_token())?;
+        Ok(EcrToken {
+            username,
+            secret,
+            expires_at,
+        })
+    }
+}
+
+// ECR hands out base64("AWS:<password>").
+fn decode_authorization_token(token: Option<&str>) -> Result<(String, String)> {
+    let decoded = token
+        .and_then(|token| STANDARD.decode(token).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    match decoded.as_deref().and_then(|d| d.split_once(':')) {
+        Some((username, secret)) => Ok((username.to_string(), secret.to_string())),
+        None => {
+            error!("ecr.decode_authorization_token invalid token");
+            Err(anyhow!(MyErrors::InvalidAuthorizationToken))
+        }
+    }
+}
+
+fn ecr_client(credentials: &AWScredentials, region: &str) -> Result<aws_sdk_ecr::Client> {
+    let config = sdk_config(Some(credentials.credentials_provider()), region)?;
+    Ok(aws_sdk_ecr::Client::new(&config))
+}
+
+// The `docker-credential-ssologinlite` helper. Registries are mapped to
+// profiles by the ecr_registries table of ssologinlite.toml.
+#[derive(Debug, Default, Clone)]
+pub struct DockerCredentialHelper {
+    registries: HashMap<String, String>,
+}
+
+impl DockerCredentialHelper {
+    pub fn new(config: &ProgramConfig) -> DockerCredentialHelper {
+        DockerCredentialHelper {
+            registries: config
+                .ecr_registries
+                .iter()
+                .map(|(host, profile)| (registry_host(host), profile.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn profile_for(&self, server_url: &str) -> Option<&str> {
+        self.registries
+            .get(&registry_host(server_url))
+            .map(String::as_str)
+    }
+
+    // Credentials for `server_url`, None when no profile is mapped to it.
+    pub async fn get(&self, server_url: &str) -> Result<Option<DockerCredentials>> {
+        let profile_name = match self.profile_for(server_url) {
+            Some(profile_name) => profile_name,
+            None => {
+                debug!(
+                    "ecr.DockerCredentialHelper.get no profile for {}",
+                    server_url
+                );
+                return Ok(None);
+            }
+        };
+        let registry = EcrRegistry::from_server_url(server_url)?;
+        let profile = Profiles::get_profile(profile_name.to_string())?;
+        if let Profile::OtherProfile = profile {
+            return Err(anyhow!(Error::ProfileNotFound(profile_name.to_string())));
+        }
+        let token = EcrToken::get(&profile, profile_name, &registry).await?;
+        Ok(Some(DockerCredentials {
+            ServerURL: server_url.trim().to_string(),
+            Username: token.username,
+            Secret: token.secret,
+        }))
+    }
+
+    // Server URL -> username of every mapped registry.
+    pub fn list(&self) -> HashMap<String, String> {
+        self.registries
+            .keys()
+            .map(|host| (host.clone(), "AWS".to_string()))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+enum MyErrors {
+    NotAnEcrRegistry(String),
+    GetAuthorizationToken,
+    InvalidAuthorizationToken,
+}
+
+impl std::fmt::Display for MyErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotAnEcrRegistry(host) => write!(f, "{} is not an ECR registry", host),
+            Self::GetAuthorizationToken => write!(f, "Error getting ECR authorization token!"),
+            Self::InvalidAuthorizationToken => {
+                write!(f, "ECR returned an unreadable authorization token")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_host() {
+        assert_eq!(
+            registry_host("https://123456789012.dkr.ecr.us-west-2.amazonaws.com/v2/"),
+            "123456789012.dkr.ecr.us-west-2.amazonaws.com"
+        );
+        assert_eq!(
+            registry_host(" 123456789012.DKR.ECR.us-west-2.amazonaws.com\n"),
+            "123456789012.dkr.ecr.us-west-2.amazonaws.com"
+        );
+    }
+
+    #[test]
+    fn test_ecr_registry_from_server_url() {
+        let registry =
+            EcrRegistry::from_server_url("https://123456789012.dkr.ecr.eu-west-1.amazonaws.com")
+                .unwrap();
+        assert_eq!(registry.account, "123456789012");
+        assert_eq!(registry.region, "eu-west-1");
+        let china =
+            EcrRegistry::from_server_url("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn")
+                .unwrap();
+        assert_eq!(china.region, "cn-north-1");
+        let fips =
+            EcrRegistry::from_server_url("123456789012.dkr.ecr-fips.us-east-1.amazonaws.com")
+                .unwrap();
+        assert_eq!(fips.region, "us-east-1");
+        assert!(EcrRegistry::from_server_url("docker.io").is_err());
+        assert!(EcrRegistry::from_server_url("1234.dkr.ecr.us-east-1.amazonaws.com").is_err());
+    }
+
+    #[test]
+    fn test_decode_authorization_token() {
+        let token = STANDARD.encode("AWS:secret-password");
+        assert_eq!(
+            decode_authorization_token(Some(&token)).unwrap(),
+            ("AWS".to_string(), "secret-password".to_string())
+        );
+        assert!(decode_authorization_token(Some("not base64!")).is_err());
+        assert!(decode_authorization_token(Some(&STANDARD.encode("no-colon"))).is_err());
+        assert!(decode_authorization_token(None).is_err());
+    }
+
+    #[test]
+    fn test_ecr_token_is_fresh() {
+        let token = |expires_at: DateTime<Utc>| EcrToken {
+            username: "AWS".to_string(),
+            secret: "s".to_string(),
+            expires_at: expires_at.to_rfc3339(),
+        };
+        assert!(token(Utc::now() + Duration::hours(12)).is_fresh());
+        assert!(!token(Utc::now() + Duration::minutes(2)).is_fresh());
+        assert!(!token(Utc::now() - Duration::hours(1)).is_fresh());
+    }
+
+    #[test]
+    fn test_helper_maps_registries_to_profiles() {
+        let mut config = ProgramConfig::default();
+        config.ecr_registries.insert(
+            "https://123456789012.dkr.ecr.us-west-2.amazonaws.com".to_string(),
+            "prod".to_string(),
+        );
+        let helper = DockerCredentialHelper::new(&config);
+        assert_eq!(
+            helper.profile_for("123456789012.dkr.ecr.us-west-2.amazonaws.com"),
+            Some("prod")
+        );
+        assert_eq!(
+            helper.profile_for("https://123456789012.dkr.ecr.us-west-2.amazonaws.com/"),
+            Some("prod")
+        );
+        assert!(helper
+            .profile_for("210987654321.dkr.ecr.us-west-2.amazonaws.com")
+            .is_none());
+        assert_eq!(
+            helper.list(),
+            HashMap::from([(
+                "123456789012.dkr.ecr.us-west-2.amazonaws.com".to_string(),
+                "AWS".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_helper_get_unmapped_registry() {
+        let helper = DockerCredentialHelper::default();
+        assert!(helper.get("docker.io").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,523 @@
+use crate::aws_credentials::{AWScredentials, RoleToAssume};
+use crate::aws_profile::Profile;
+use crate::config::ProgramConfig;
+use crate::eks::{decode_token, eks_credentials, Status};
+use crate::error::Error;
+use crate::http_client::reqwest_builder;
+use crate::kubeconfig::eks_client;
+use anyhow::{anyhow, Result};
+use aws_sdk_eks::types::AuthenticationMode;
+use log::{debug, error, info};
+
+const CLUSTER_ID_HEADER: &str = "x-k8s-aws-id";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckResult {
+    Ok,
+    // Not a failure, but worth a look, e.g. access the check can't confirm.
+    Warning,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub result: CheckResult,
+    pub detail: String,
+}
+
+// The steps `eks doctor` ran, in order. A failed step skips the ones that
+// depend on it, so the first failure is the one to fix.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallerIdentity {
+    pub arn: String,
+    pub account: String,
+    pub user_id: String,
+}
+
+impl DoctorReport {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.result != CheckResult::Failed)
+    }
+
+    fn ok(&mut self, name: &'static str, detail: String) {
+        self.push(name, CheckResult::Ok, detail);
+    }
+
+    fn failed(&mut self, name: &'static str, detail: String) {
+        self.push(name, CheckResult::Failed, detail);
+    }
+
+    fn skipped(&mut self, names: &[&'static str]) {
+        for name in names {
+            self.push(
+                name,
+                CheckResult::Skipped,
+                "earlier step failed".to_string(),
+            );
+        }
+    }
+
+    fn push(&mut self, name: &'static str, result: CheckResult, detail: String) {
+        self.checks.push(Check {
+            name,
+            result,
+            detail,
+        });
+    }
+
+    // Signs a token the way `eks` does, replays it against STS and looks the
+    // identity up in the cluster's access entries.
+    pub async fn run(
+        profile: &Profile,
+        profile_name: &str,
+        region: &str,
+        cluster: &str,
+        sts_endpoint: Option<&str>,
+        role: Option<&RoleToAssume>,
+    ) -> DoctorReport {
+        info!("Checking EKS access to {}", cluster);
+        let mut report = DoctorReport::default();
+        let credentials = match eks_credentials(profile, profile_name, region, role).await {
+            Ok(credentials) => {
+                let source = match role {
+                    Some(role) => {
+                        format!("{} assumed from profile {}", role.role_arn, profile_name)
+                    }
+                    None => format!("profile {}", profile_name),
+                };
+                report.ok("credentials", source);
+                credentials
+            }
+            Err(e) => {
+                report.failed("credentials", e.to_string());
+                report.skipped(&["token", "identity", "cluster", "access entry"]);
+                return report;
+            }
+        };
+        let url = match signed_url(credentials.clone(), region, cluster, sts_endpoint) {
+            Ok(url) => {
+                report.ok("token", format!("presigned {}", url_host(&url)));
+                url
+            }
+            Err(e) => {
+                report.failed("token", e.to_string());
+                report.skipped(&["identity", "cluster", "access entry"]);
+                return report;
+            }
+        };
+        let identity = match get_caller_identity(&url, cluster).await {
+            Ok(identity) => {
+                report.ok("identity", identity.arn.clone());
+                identity
+            }
+            Err(e) => {
+                report.failed("identity", e.to_string());
+                report.skipped(&["cluster", "access entry"]);
+                return report;
+            }
+        };
+        let client = match eks_client(&credentials, region) {
+            Ok(client) => client,
+            Err(e) => {
+                report.failed("cluster", e.to_string());
+                report.skipped(&["access entry"]);
+                return report;
+            }
+        };
+        let mode = match authentication_mode(&client, cluster).await {
+            Ok(mode) => {
+                report.ok(
+                    "cluster",
+                    format!("{} in {}, {}", cluster, region, mode.as_str()),
+                );
+                mode
+            }
+            Err(e) => {
+                report.failed("cluster", e.to_string());
+                report.skipped(&["access entry"]);
+                return report;
+            }
+        };
+        if mode == AuthenticationMode::ConfigMap {
+            report.push(
+                "access entry",
+                CheckResult::Skipped,
+                "cluster only reads the aws-auth ConfigMap, check it with kubectl".to_string(),
+            );
+            return report;
+        }
+        match list_access_entries(&client, cluster).await {
+            Ok(entries) => {
+                let (result, detail) = access_entry_check(&mode, cluster, &identity.arn, &entries);
+                report.push("access entry", result, detail);
+            }
+            Err(e) => report.failed("access entry", e.to_string()),
+        }
+        report
+    }
+}
+
+// The access entry covering `arn`, if any. A cluster that also reads the
+// aws-auth ConfigMap may map the identity there instead, so a missing entry
+// only fails in API mode.
+fn access_entry_check(
+    mode: &AuthenticationMode,
+    cluster: &str,
+    arn: &str,
+    entries: &[String],
+) -> (CheckResult, String) {
+    if let Some(entry) = entries.iter().find(|e| principal_matches(arn, e)) {
+        return (CheckResult::Ok, entry.clone());
+    }
+    let principal = iam_principal_arn(arn).unwrap_or(arn.to_string());
+    match mode {
+        AuthenticationMode::ApiAndConfigMap => (
+            CheckResult::Warning,
+            format!(
+                "no access entry on {} for {}; may still be mapped in aws-auth",
+                cluster, principal
+            ),
+        ),
+        _ => (
+            CheckResult::Failed,
+            format!("no access entry on {} for {}", cluster, principal),
+        ),
+    }
+}
+
+impl std::fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for check in self.checks.iter() {
+            let result = match check.result {
+                CheckResult::Ok => "[ok]",
+                CheckResult::Warning => "[warn]",
+                CheckResult::Failed => "[FAILED]",
+                CheckResult::Skipped => "[skipped]",
+            };
+            writeln!(f, "{:<10} {:<13} {}", result, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+// Our own token for the cluster, decoded back into its presigned URL.
+fn signed_url(
+    credentials: AWScredentials,
+    region: &str,
+    cluster: &str,
+    sts_endpoint: Option<&str>,
+) -> Result<String> {
+    let status =
+        Status::from_credentials_at(credentials, region.to_string(), cluster, sts_endpoint)?;
+    decode_token(&status.token)
+}
+
+fn url_host(url: &str) -> &str {
+    let rest = url.strip_prefix("https://").unwrap_or(url);
+    rest.split(['/', '?']).next().unwrap_or(rest)
+}
+
+// Calls the presigned URL the way the EKS authenticator does.
+async fn get_caller_identity(url: &str, cluster: &str) -> Result<CallerIdentity> {
+    debug!("eks_doctor.get_caller_identity {}", url_host(url));
+    let client = reqwest_builder(&ProgramConfig::new()?)?.build()?;
+    let response = match client
+        .get(url)
+        .header(CLUSTER_ID_HEADER, cluster)
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            error!("eks_doctor.get_caller_identity {}", e);
+            return Err(anyhow!(Error::from_reqwest(e))
+                .context(MyErrors::StsUnreachable(url_host(url).to_string())));
+        }
+    };
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        error!("eks_doctor.get_caller_identity {} {}", status, body);
+        return Err(anyhow!(MyErrors::StsRejected(
+            status.as_u16(),
+            sts_error(&body).unwrap_or(body)
+        )));
+    }
+    parse_caller_identity(&body)
+}
+
+fn xml_value<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let start = body.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + body[start..].find(&format!("</{}>", tag))?;
+    Some(body[start..end].trim())
+}
+
+fn parse_caller_identity(body: &str) -> Result<CallerIdentity> {
+    match (
+        xml_value(body, "Arn"),
+        xml_value(body, "Account"),
+        xml_value(body, "UserId"),
+    ) {
+        (Some(arn), Some(account), Some(user_id)) => Ok(CallerIdentity {
+            arn: arn.to_string(),
+            account: account.to_string(),
+            user_id: user_id.to_string(),
+        }),
+        _ => Err(anyhow!(MyErrors::InvalidStsResponse)),
+    }
+}
+
+// "Code: Message" of an STS error document.
+fn sts_error(body: &str) -> Option<String> {
+    let code = xml_value(body, "Code")?;
+    match xml_value(body, "Message") {
+        Some(message) => Some(format!("{}: {}", code, message)),
+        None => Some(code.to_string()),
+    }
+}
+
+// (partition, account, kind, name) of a role or user ARN. An assumed-role
+// session maps to its role; paths are dropped since STS drops them too.
+fn principal(arn: &str) -> Option<(&str, &str, &str, &str)> {
+    let parts: Vec<&str> = arn.splitn(6, ':').collect();
+    if parts.len() != 6 || parts[0] != "arn" {
+        return None;
+    }
+    let (partition, service, account, resource) = (parts[1], parts[2], parts[4], parts[5]);
+    let segments: Vec<&str> = resource.split('/').collect();
+    match (service, segments.as_slice()) {
+        ("sts", ["assumed-role", role, _session]) => Some((partition, account, "role", role)),
+        ("iam", [kind @ ("role" | "user"), .., name]) => Some((partition, account, kind, name)),
+        _ => None,
+    }
+}
+
+// The IAM ARN an access entry would use for `arn`.
+pub fn iam_principal_arn(arn: &str) -> Option<String> {
+    let (partition, account, kind, name) = principal(arn)?;
+    Some(format!(
+        "arn:{}:iam::{}:{}/{}",
+        partition, account, kind, name
+    ))
+}
+
+// Whether the access entry `entry` covers the caller `arn`.
+pub fn principal_matches(arn: &str, entry: &str) -> bool {
+    match (principal(arn), principal(entry)) {
+        (Some(caller), Some(entry)) => caller == entry,
+        _ => arn == entry,
+    }
+}
+
+async fn authentication_mode(
+    client: &aws_sdk_eks::Client,
+    cluster: &str,
+) -> Result<AuthenticationMode> {
+    let output = match client.describe_cluster().name(cluster).send().await {
+        Ok(output) => output,
+        Err(e) => {
+            error!("eks_doctor.authentication_mode {}", e);
+            return Err(
+                anyhow!(Error::from_sdk(e)).context(MyErrors::DescribeCluster(cluster.to_string()))
+            );
+        }
+    };
+    Ok(output
+        .cluster()
+        .and_then(|c| c.access_config())
+        .and_then(|c| c.authentication_mode())
+        .cloned()
+        .unwrap_or(AuthenticationMode::ConfigMap))
+}
+
+async fn list_access_entries(client: &aws_sdk_eks::Client, cluster: &str) -> Result<Vec<String>> {
+    let mut entries = Vec::new();
+    let mut next_token: Option<String> = None;
+    loop {
+        let output = match client
+            .list_access_entries()
+            .cluster_name(cluster)
+            .set_next_token(next_token)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("eks_doctor.list_access_entries {}", e);
+                return Err(anyhow!(Error::from_sdk(e))
+                    .context(MyErrors::ListAccessEntries(cluster.to_string())));
+            }
+        };
+        entries.extend(output.access_entries().iter().cloned());
+        next_token = output.next_token;
+        if next_token.is_none() {
+            return Ok(entries);
+        }
+    }
+}
+
+#[derive(Debug)]
+enum MyErrors {
+    StsUnreachable(String),
+    StsRejected(u16, String),
+    InvalidStsResponse,
+    DescribeCluster(String),
+    ListAccessEntries(String),
+}
+
+impl std::fmt::Display for MyErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StsUnreachable(host) => write!(f, "Could not reach {}", host),
+            Self::StsRejected(status, message) => {
+                write!(f, "STS rejected the token ({}): {}", status, message)
+            }
+            Self::InvalidStsResponse => write!(f, "Could not read the GetCallerIdentity response"),
+            Self::DescribeCluster(name) => write!(f, "Could not describe EKS cluster {}!", name),
+            Self::ListAccessEntries(name) => {
+                write!(f, "Could not list the access entries of {}!", name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: &str = r#"<GetCallerIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <GetCallerIdentityResult>
+    <Arn>arn:aws:sts::123456789012:assumed-role/AWSReservedSSO_Admin_0123456789abcdef/jdoe</Arn>
+    <UserId>AROAEXAMPLE:jdoe</UserId>
+    <Account>123456789012</Account>
+  </GetCallerIdentityResult>
+</GetCallerIdentityResponse>"#;
+
+    const ERROR: &str = r#"<ErrorResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>SignatureDoesNotMatch</Code>
+    <Message>Signature expired</Message>
+  </Error>
+</ErrorResponse>"#;
+
+    #[test]
+    fn test_parse_caller_identity() {
+        let identity = parse_caller_identity(IDENTITY).unwrap();
+        assert_eq!(
+            identity.arn,
+            "arn:aws:sts::123456789012:assumed-role/AWSReservedSSO_Admin_0123456789abcdef/jdoe"
+        );
+        assert_eq!(identity.account, "123456789012");
+        assert_eq!(identity.user_id, "AROAEXAMPLE:jdoe");
+        assert!(parse_caller_identity(ERROR).is_err());
+    }
+
+    #[test]
+    fn test_sts_error() {
+        assert_eq!(
+            sts_error(ERROR).as_deref(),
+            Some("SignatureDoesNotMatch: Signature expired")
+        );
+        assert!(sts_error("not xml").is_none());
+    }
+
+    #[test]
+    fn test_iam_principal_arn() {
+        assert_eq!(
+            iam_principal_arn("arn:aws:sts::123456789012:assumed-role/eks-admin/jdoe").as_deref(),
+            Some("arn:aws:iam::123456789012:role/eks-admin")
+        );
+        assert_eq!(
+            iam_principal_arn("arn:aws-cn:iam::123456789012:user/ops/jdoe").as_deref(),
+            Some("arn:aws-cn:iam::123456789012:user/jdoe")
+        );
+        assert!(iam_principal_arn("arn:aws:sts::123456789012:federated-user/jdoe").is_none());
+    }
+
+    #[test]
+    fn test_principal_matches() {
+        let caller = "arn:aws:sts::123456789012:assumed-role/AWSReservedSSO_Admin_0123/jdoe";
+        assert!(principal_matches(
+            caller,
+            "arn:aws:iam::123456789012:role/aws-reserved/sso.amazonaws.com/AWSReservedSSO_Admin_0123"
+        ));
+        assert!(principal_matches(
+            caller,
+            "arn:aws:iam::123456789012:role/AWSReservedSSO_Admin_0123"
+        ));
+        assert!(!principal_matches(
+            caller,
+            "arn:aws:iam::210987654321:role/AWSReservedSSO_Admin_0123"
+        ));
+        assert!(!principal_matches(
+            caller,
+            "arn:aws:iam::123456789012:user/AWSReservedSSO_Admin_0123"
+        ));
+        assert!(principal_matches(
+            "arn:aws:iam::123456789012:user/jdoe",
+            "arn:aws:iam::123456789012:user/jdoe"
+        ));
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("https://sts.us-west-2.amazonaws.com/?Action=GetCallerIdentity"),
+            "sts.us-west-2.amazonaws.com"
+        );
+        assert_eq!(
+            url_host("https://vpce-1.sts.us-west-2.vpce.amazonaws.com?Action=X"),
+            "vpce-1.sts.us-west-2.vpce.amazonaws.com"
+        );
+    }
+
+    #[test]
+    fn test_access_entry_check_by_mode() {
+        let arn = "arn:aws:sts::123456789012:assumed-role/Dev/me";
+        let entries = vec!["arn:aws:iam::123456789012:role/Admin".to_string()];
+        let (result, detail) =
+            access_entry_check(&AuthenticationMode::ApiAndConfigMap, "main", arn, &entries);
+        assert_eq!(result, CheckResult::Warning);
+        assert!(detail.ends_with("may still be mapped in aws-auth"));
+        let (result, _) = access_entry_check(&AuthenticationMode::Api, "main", arn, &entries);
+        assert_eq!(result, CheckResult::Failed);
+
+        let entries = vec!["arn:aws:iam::123456789012:role/Dev".to_string()];
+        for mode in [AuthenticationMode::Api, AuthenticationMode::ApiAndConfigMap] {
+            assert_eq!(
+                access_entry_check(&mode, "main", arn, &entries),
+                (CheckResult::Ok, entries[0].clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_report_display_and_passed() {
+        let mut report = DoctorReport::default();
+        report.ok("credentials", "profile prod".to_string());
+        report.push(
+            "access entry",
+            CheckResult::Warning,
+            "not found".to_string(),
+        );
+        assert!(report.passed());
+        report.failed("identity", "STS rejected the token".to_string());
+        report.skipped(&["cluster"]);
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_string(),
+            "[ok]       credentials   profile prod\n\
+             [warn]     access entry  not found\n\
+             [FAILED]   identity      STS rejected the token\n\
+             [skipped]  cluster       earlier step failed\n"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use aws_smithy_runtime_api::client::result::SdkError;
+use aws_smithy_types::error::metadata::ProvideErrorMetadata;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Service error codes meaning the caller is not allowed to do this.
+const DENIED_CODES: [&str; 5] = [
+    "AccessDenied",
+    "AccessDeniedException",
+    "ForbiddenException",
+    "UnauthorizedException",
+    "UnauthorizedOperation",
+];
+
+// Failures callers may want to react to, each with its own exit code. Modules
+// raise them inside anyhow errors, so they survive added context; exit_code
+// finds them again. Everything else exits 1.
+#[derive(Debug)]
+pub enum Error {
+    // The SSO session has expired and can't be renewed without a browser.
+    SsoExpired(String),
+    // AWS refused the request, e.g. a role the SSO user can't assume.
+    AuthorizationDenied(BoxError),
+    // AWS could not be reached, or did not answer in time.
+    Network(BoxError),
+    ProfileNotFound(String),
+    // A config file is missing or can't be parsed.
+    ConfigParse { file: String, source: BoxError },
+    // The cache is corrupt or can't be written.
+    CacheCorrupt { file: String, source: BoxError },
+    // A setting the command needs is neither given nor configured.
+    MissingSetting(String),
+    Other(anyhow::Error),
+}
+
+pub const EXIT_OTHER: u8 = 1;
+pub const EXIT_SSO_EXPIRED: u8 = 3;
+pub const EXIT_AUTHORIZATION_DENIED: u8 = 4;
+pub const EXIT_NETWORK: u8 = 5;
+pub const EXIT_PROFILE_NOT_FOUND: u8 = 6;
+pub const EXIT_CONFIG_PARSE: u8 = 7;
+pub const EXIT_CACHE_CORRUPT: u8 = 8;
+pub const EXIT_MISSING_SETTING: u8 = 9;
+
+impl Error {
+    // Classifies a failed AWS SDK call. The SDK error stays the source.
+    pub fn from_sdk<E, R>(e: SdkError<E, R>) -> Error
+    where
+        E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+        R: std::fmt::Debug + Send + Sync + 'static,
+    {
+        match &e {
+            SdkError::DispatchFailure(_) | SdkError::TimeoutError(_) => Error::Network(Box::new(e)),
+            SdkError::ServiceError(service)
+                if DENIED_CODES.contains(&service.err().code().unwrap_or_default()) =>
+            {
+                Error::AuthorizationDenied(Box::new(e))
+            }
+            _ => Error::Other(anyhow::Error::new(e)),
+        }
+    }
+
+    // A failed HTTP call made without the SDK.
+    pub fn from_reqwest(e: reqwest::Error) -> Error {
+        match e.is_connect() || e.is_timeout() || e.is_request() {
+            true => Error::Network(Box::new(e)),
+            false => Error::Other(anyhow::Error::new(e)),
+        }
+    }
+
+    // The Error an anyhow error carries, or Other.
+    pub fn from_anyhow(e: anyhow::Error) -> Error {
+        match e.downcast::<Error>() {
+            Ok(e) => e,
+            Err(e) => Error::Other(e),
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::SsoExpired(_) => EXIT_SSO_EXPIRED,
+            Error::AuthorizationDenied(_) => EXIT_AUTHORIZATION_DENIED,
+            Error::Network(_) => EXIT_NETWORK,
+            Error::ProfileNotFound(_) => EXIT_PROFILE_NOT_FOUND,
+            Error::ConfigParse { .. } => EXIT_CONFIG_PARSE,
+            Error::CacheCorrupt { .. } => EXIT_CACHE_CORRUPT,
+            Error::MissingSetting(_) => EXIT_MISSING_SETTING,
+            Error::Other(_) => EXIT_OTHER,
+        }
+    }
+}
+
+// The exit code of the first Error in e's chain.
+pub fn exit_code(e: &anyhow::Error) -> u8 {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<Error>())
+        .map_or(EXIT_OTHER, Error::exit_code)
+}
+
+// What to do about a failure, in the user's terms. Added as context where
+// the cause is known; report prints it on its own line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint(pub String);
+
+impl std::fmt::Display for Hint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Adds the hint, if any, as context of e.
+pub fn with_hint(e: anyhow::Error, hint: Option<Hint>) -> anyhow::Error {
+    match hint {
+        Some(hint) => e.context(hint),
+        None => e,
+    }
+}
+
+// Whether the call failed before AWS answered.
+pub fn is_unreachable<E, R>(e: &SdkError<E, R>) -> bool {
+    matches!(e, SdkError::DispatchFailure(_) | SdkError::TimeoutError(_))
+}
+
+// The hint for an unreachable SSO or OIDC endpoint in sso_region.
+pub fn unreachable_hint(sso_region: &str) -> Hint {
+    Hint(format!(
+        "the SSO endpoint in {} is unreachable, check the network and sso_region",
+        sso_region
+    ))
+}
+
+// The hint closest to the top of e, else a generic one for its Error.
+pub fn hint(e: &anyhow::Error) -> Option<String> {
+    if let Some(hint) = e.downcast_ref::<Hint>() {
+        return Some(hint.0.clone());
+    }
+    match e.chain().find_map(|cause| cause.downcast_ref::<Error>())? {
+        Error::SsoExpired(profile) => Some(format!(
+            "run `ssologinlite token --profile {}` in a terminal to log in again",
+            profile
+        )),
+        Error::Network(_) => {
+            Some("check the network connection and any proxy settings".to_string())
+        }
+        Error::ProfileNotFound(_) => {
+            Some("run `ssologinlite setup` to manage the profiles of ~/.aws/config".to_string())
+        }
+        Error::CacheCorrupt { file, .. } => Some(format!("check the permissions of {}", file)),
+        _ => None,
+    }
+}
+
+// The failure as printed to stderr: the error, each distinct cause, then the
+// hint. stdout is left to the command's output, e.g. for credential_process.
+pub fn report(e: &anyhow::Error) -> String {
+    let hint = hint(e);
+    let mut lines: Vec<String> = Vec::new();
+    for cause in e.chain().map(|cause| cause.to_string()) {
+        // Hints are context too; they get their own line.
+        if Some(&cause) == hint.as_ref() || lines.last() == Some(&cause) {
+            continue;
+        }
+        lines.push(cause);
+    }
+    let mut report = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        match i {
+            0 => report.push_str(&format!("Error: {}\n", line)),
+            _ => report.push_str(&format!("  caused by: {}\n", line)),
+        }
+    }
+    if let Some(hint) = hint {
+        report.push_str(&format!("hint: {}\n", hint));
+    }
+    report
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SsoExpired(profile) => {
+                write!(f, "SSO session for profile {} has expired", profile)
+            }
+            Self::AuthorizationDenied(_) => write!(f, "Authorization denied"),
+            Self::Network(_) => write!(f, "Network error"),
+            Self::ProfileNotFound(profile) => write!(f, "Profile {} not found", profile),
+            Self::ConfigParse { file, .. } => write!(f, "Could not read {}", file),
+            Self::CacheCorrupt { file, .. } => write!(f, "Cache {} is unusable", file),
+            Self::MissingSetting(message) => write!(f, "{}", message),
+            Self::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::AuthorizationDenied(source)
+            | Self::Network(source)
+            | Self::ConfigParse { source, .. }
+            | Self::CacheCorrupt { source, .. } => Some(source.as_ref()),
+            Self::Other(e) => e.source(),
+            Self::SsoExpired(_) | Self::ProfileNotFound(_) | Self::MissingSetting(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+    use aws_sdk_sts::operation::assume_role::AssumeRoleError;
+    use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+    use aws_smithy_runtime_api::client::result::ConnectorError;
+    use aws_smithy_runtime_api::http::StatusCode;
+    use aws_smithy_types::body::SdkBody;
+    use aws_smithy_types::error::ErrorMetadata;
+
+    fn service_error(code: &str) -> SdkError<AssumeRoleError, HttpResponse> {
+        let meta = ErrorMetadata::builder()
+            .code(code)
+            .message("no way")
+            .build();
+        SdkError::service_error(
+            AssumeRoleError::generic(meta),
+            HttpResponse::new(StatusCode::try_from(403).unwrap(), SdkBody::empty()),
+        )
+    }
+
+    #[test]
+    fn test_from_sdk_classifies() {
+        let denied = Error::from_sdk(service_error("AccessDenied"));
+        assert_eq!(denied.exit_code(), EXIT_AUTHORIZATION_DENIED);
+        let other = Error::from_sdk(service_error("ExpiredTokenException"));
+        assert_eq!(other.exit_code(), EXIT_OTHER);
+        let network: SdkError<AssumeRoleError, HttpResponse> = SdkError::dispatch_failure(
+            ConnectorError::io(Box::new(std::io::Error::other("connection refused"))),
+        );
+        let network = Error::from_sdk(network);
+        assert_eq!(network.exit_code(), EXIT_NETWORK);
+        assert!(std::error::Error::source(&network).is_some());
+    }
+
+    #[test]
+    fn test_exit_code_survives_context() {
+        let e = Err::<(), _>(anyhow!(Error::ProfileNotFound("prod".to_string())))
+            .context("Could not get credentials")
+            .unwrap_err();
+        assert_eq!(exit_code(&e), EXIT_PROFILE_NOT_FOUND);
+        assert!(matches!(
+            Error::from_anyhow(e),
+            Error::ProfileNotFound(profile) if profile == "prod"
+        ));
+        assert_eq!(exit_code(&anyhow!("plain")), EXIT_OTHER);
+    }
+
+    #[test]
+    fn test_report_puts_hint_last() {
+        let e = Err::<(), _>(anyhow!(Error::from_sdk(service_error("AccessDenied"))))
+            .context(Hint("check the trust policy of role ops".to_string()))
+            .context("Error assuming role!")
+            .unwrap_err();
+        let report = report(&e);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "Error: Error assuming role!");
+        assert_eq!(lines[1], "  caused by: Authorization denied");
+        assert!(lines[2].starts_with("  caused by: service error"));
+        assert_eq!(
+            lines.last(),
+            Some(&"hint: check the trust policy of role ops")
+        );
+        assert_eq!(report.matches("trust policy").count(), 1);
+    }
+
+    #[test]
+    fn test_generic_hints() {
+        let e =
+            anyhow!(Error::SsoExpired("prod".to_string())).context("Error getting credentials!");
+        assert_eq!(
+            hint(&e).as_deref(),
+            Some("run `ssologinlite token --profile prod` in a terminal to log in again")
+        );
+        assert!(hint(&anyhow!("plain")).is_none());
+        assert_eq!(report(&anyhow!("plain")), "Error: plain\n");
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let mut codes = vec![
+            EXIT_OTHER,
+            EXIT_SSO_EXPIRED,
+            EXIT_AUTHORIZATION_DENIED,
+            EXIT_NETWORK,
+            EXIT_PROFILE_NOT_FOUND,
+            EXIT_CONFIG_PARSE,
+            EXIT_CACHE_CORRUPT,
+            EXIT_MISSING_SETTING,
+        ];
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 8);
+        // 2 is clap's usage error.
+        assert!(!codes.contains(&2));
+    }
+}
@@ -1,11 +1,56 @@
-use crate::constants::AWS_CONFIG;
+use crate::constants::{
+    AWS_CONFIG, AWS_CREDENTIALS, CARGO_HOME, CONFIG_FILE, ENV_AWS_CONFIG_FILE,
+    ENV_AWS_CREDENTIALS_FILE, ENV_CARGO_HOME, ENV_HOME, ENV_KUBECONFIG, ENV_NPMRC, ENV_PIP_CONF,
+    KUBECONFIG, NPMRC, PIP_CONF, PROFILES, PROGRAM_FOLDER, PROGRAM_NAME,
+};
 use anyhow::{anyhow, Result};
 use chrono::Local;
 use home::home_dir;
 use log::{debug, error};
 use std::ffi::OsString;
-use std::fs::{copy, metadata, set_permissions, Permissions};
-use std::os::unix::fs::PermissionsExt;
+use std::fs::{copy, create_dir_all, metadata, remove_file, rename, set_permissions};
+use std::fs::{File, OpenOptions, Permissions};
+use std::future::Future;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Distinguishes temp files of concurrent writers within one process.
+static WRITE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Locations set by a library caller, taking precedence over the environment
+// variables for the code run inside with_paths. Task-local, so clients
+// with different paths can share a process.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PathOverrides {
+    // Like SSOLOGINLITE_HOME: profiles.json, the cache and the logs.
+    pub home: Option<PathBuf>,
+    pub aws_config: Option<PathBuf>,
+    pub aws_credentials: Option<PathBuf>,
+    // The program config file, e.g. /etc/myservice/ssologinlite.toml.
+    pub program_config: Option<PathBuf>,
+}
+
+tokio::task_local! {
+    static PATH_OVERRIDES: PathOverrides;
+}
+
+pub async fn with_paths<F: Future>(overrides: PathOverrides, f: F) -> F::Output {
+    PATH_OVERRIDES.scope(overrides, f).await
+}
+
+pub fn with_paths_sync<R>(overrides: PathOverrides, f: impl FnOnce() -> R) -> R {
+    PATH_OVERRIDES.sync_scope(overrides, f)
+}
+
+fn path_override(pick: impl FnOnce(&PathOverrides) -> Option<PathBuf>) -> Option<OsString> {
+    PATH_OVERRIDES
+        .try_with(|overrides| pick(overrides))
+        .ok()
+        .flatten()
+        .map(PathBuf::into_os_string)
+}
 
 pub fn backup_config() -> Result<()> {
     let aws_config = get_aws_config()?;
@@ -57,9 +102,170 @@ pub fn get_home_os_string(input: &str) -> Result<OsString> {
     }
 }
 
+// An environment variable that is set to a non-empty value.
+fn env_path(name: &str) -> Option<OsString> {
+    std::env::var_os(name).filter(|value| !value.is_empty())
+}
+
+// Expand a leading "~/" the way the AWS CLI does for its *_FILE variables.
+fn expand_home(path: OsString) -> Result<OsString> {
+    match path.to_str().and_then(|p| p.strip_prefix("~/")) {
+        Some(rest) => get_home_os_string(rest),
+        None => Ok(path),
+    }
+}
+
 pub fn get_aws_config() -> Result<OsString> {
     debug!("getting aws config file");
-    get_home_os_string(AWS_CONFIG)
+    if let Some(path) = path_override(|o| o.aws_config.clone()) {
+        return Ok(path);
+    }
+    match env_path(ENV_AWS_CONFIG_FILE) {
+        Some(path) => expand_home(path),
+        None => get_home_os_string(AWS_CONFIG),
+    }
+}
+
+pub fn get_aws_credentials() -> Result<OsString> {
+    debug!("getting aws credentials file");
+    if let Some(path) = path_override(|o| o.aws_credentials.clone()) {
+        return Ok(path);
+    }
+    match env_path(ENV_AWS_CREDENTIALS_FILE) {
+        Some(path) => expand_home(path),
+        None => get_home_os_string(AWS_CREDENTIALS),
+    }
+}
+
+// kubectl merges every file listed in KUBECONFIG and writes new entries to
+// the first one, so do the same.
+pub fn get_kubeconfig() -> Result<OsString> {
+    debug!("getting kubeconfig file");
+    let first = env_path(ENV_KUBECONFIG)
+        .and_then(|paths| std::env::split_paths(&paths).find(|p| !p.as_os_str().is_empty()));
+    match first {
+        Some(path) => expand_home(path.into_os_string()),
+        None => get_home_os_string(KUBECONFIG),
+    }
+}
+
+// npm's per-user config.
+pub fn get_npmrc() -> Result<OsString> {
+    match env_path(ENV_NPMRC) {
+        Some(path) => expand_home(path),
+        None => get_home_os_string(NPMRC),
+    }
+}
+
+// pip's per-user config, in the XDG location pip also reads on macOS.
+pub fn get_pip_conf() -> Result<OsString> {
+    if let Some(path) = env_path(ENV_PIP_CONF) {
+        return expand_home(path);
+    }
+    match env_path("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(xdg) if xdg.is_absolute() => Ok(xdg.join("pip").join("pip.conf").into_os_string()),
+        _ => get_home_os_string(PIP_CONF),
+    }
+}
+
+// The directory holding cargo's config.toml and credentials.toml.
+pub fn get_cargo_home() -> Result<OsString> {
+    match env_path(ENV_CARGO_HOME) {
+        Some(path) => expand_home(path),
+        None => get_home_os_string(CARGO_HOME),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProgramDir {
+    Data,
+    Cache,
+    Logs,
+}
+
+// Where ssologinlite keeps its own files. SSOLOGINLITE_HOME holds everything
+// when set. Otherwise ~/.aws/ssologinlite keeps being used for every kind of
+// file once it holds profiles.json, so upgrades still find their profiles,
+// and new installs follow the XDG base directories when those are set. The
+// folder itself existing says nothing: the logger may just have created it.
+fn get_program_dir(kind: ProgramDir) -> Result<PathBuf> {
+    let home = match path_override(|o| o.home.clone()) {
+        Some(home) => Some(home),
+        None => env_path(ENV_HOME),
+    };
+    let mut dir = match home {
+        Some(home) => PathBuf::from(expand_home(home)?),
+        None => {
+            let legacy = PathBuf::from(get_home_os_string(PROGRAM_FOLDER)?);
+            let xdg_var = match kind {
+                ProgramDir::Data => "XDG_DATA_HOME",
+                ProgramDir::Cache => "XDG_CACHE_HOME",
+                ProgramDir::Logs => "XDG_STATE_HOME",
+            };
+            match env_path(xdg_var).map(PathBuf::from) {
+                _ if legacy.join(PROFILES).exists() => legacy,
+                // The spec says relative values are to be ignored.
+                Some(xdg) if xdg.is_absolute() => xdg.join(PROGRAM_NAME),
+                _ => legacy,
+            }
+        }
+    };
+    if kind == ProgramDir::Logs {
+        dir.push("logs");
+    }
+    debug!("program {:?} dir is {:?}", kind, dir);
+    Ok(dir)
+}
+
+// profiles.json, the sync base and config exports.
+pub fn get_data_file(name: &str) -> Result<OsString> {
+    Ok(get_program_dir(ProgramDir::Data)?
+        .join(name)
+        .into_os_string())
+}
+
+pub fn get_cache_file(name: &str) -> Result<OsString> {
+    Ok(get_program_dir(ProgramDir::Cache)?
+        .join(name)
+        .into_os_string())
+}
+
+pub fn get_log_file(name: &str) -> Result<OsString> {
+    Ok(get_program_dir(ProgramDir::Logs)?
+        .join(name)
+        .into_os_string())
+}
+
+// The program config, without extension: the config crate picks it.
+pub fn get_program_config() -> Result<OsString> {
+    if let Some(path) = path_override(|o| o.program_config.clone()) {
+        return Ok(path);
+    }
+    let legacy = get_home_os_string(CONFIG_FILE)?;
+    match env_path("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(xdg) if xdg.is_absolute() => {
+            let config = xdg.join(PROGRAM_NAME);
+            // A config written before XDG_CONFIG_HOME was honored stays in use.
+            match !has_config_file(&config) && has_config_file(Path::new(&legacy)) {
+                true => Ok(legacy),
+                false => Ok(config.into_os_string()),
+            }
+        }
+        _ => Ok(legacy),
+    }
+}
+
+// Whether a file named path exists, with any extension.
+fn has_config_file(path: &Path) -> bool {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .any(|entry| entry.path().is_file() && entry.path().file_stem() == Some(name)),
+        Err(_) => false,
+    }
 }
 
 pub fn get_exe_path() -> Result<OsString> {
@@ -80,11 +286,82 @@ pub fn restrict_file_permissions(file: &OsString) -> Result<()> {
     Ok(())
 }
 
+// Write data to a temp file next to `file`, fsync it and rename it into
+// place, so a crash leaves either the old content or the new one, never a
+// truncated file. The temp file is created 0600, so secrets are not readable
+// by other users even before the rename. A symlinked file, e.g. a dotfile
+// manager's ~/.aws/config, is written at its target and stays a link.
+pub fn write_file_atomic(file: &OsString, data: &[u8]) -> Result<()> {
+    let target = symlink_target(Path::new(file));
+    let path = target.as_path();
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name,
+        None => return Err(anyhow!(MyErrors::Path)),
+    };
+    create_dir_all(parent)?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        WRITE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = parent.join(tmp_name);
+    debug!("Writing {:?} through {:?}", path, tmp);
+    let result = (|| -> std::io::Result<()> {
+        let mut out = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        out.write_all(data)?;
+        out.sync_all()?;
+        rename(&tmp, path)
+    })();
+    if let Err(e) = result {
+        error!("file_helper.write_file_atomic {:?} {}", path, e);
+        let _ = remove_file(&tmp);
+        return Err(anyhow!(MyErrors::Write));
+    }
+    // Make the rename itself durable; not every filesystem supports this.
+    if let Err(e) = File::open(parent).and_then(|dir| dir.sync_all()) {
+        debug!("file_helper.write_file_atomic sync dir {}", e);
+    }
+    Ok(())
+}
+
+// The file a symlink points at, following chains of links; the path itself
+// when it is not a link. A dangling link resolves to where it points.
+fn symlink_target(path: &Path) -> PathBuf {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => {}
+        _ => return path.to_path_buf(),
+    }
+    if let Ok(target) = std::fs::canonicalize(path) {
+        return target;
+    }
+    match std::fs::read_link(path) {
+        Ok(target) => match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        },
+        Err(e) => {
+            error!("file_helper.symlink_target {:?} {}", path, e);
+            path.to_path_buf()
+        }
+    }
+}
+
 // Error definitions
 #[derive(Debug)]
 enum MyErrors {
     CurrentExe,
     Path,
+    Write,
 }
 
 impl std::fmt::Display for MyErrors {
@@ -92,12 +369,13 @@ impl std::fmt::Display for MyErrors {
         match self {
             Self::CurrentExe => write!(f, "Current exe not working!"),
             Self::Path => write!(f, "Can not find Path"),
+            Self::Write => write!(f, "Could not write file!"),
         }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use serial_test::serial;
     use std::path::Path;
@@ -107,13 +385,46 @@ mod tests {
     // where the real home may be missing or non-writable. Env-var mutation is
     // process-global, hence #[serial].
     fn with_temp_home(f: impl FnOnce(&Path)) {
+        with_temp_env(&[], f);
+    }
+
+    // Like with_temp_home, with the location overrides cleared and then
+    // `vars` applied on top; all of them are restored afterwards.
+    const OVERRIDES: [&str; 11] = [
+        ENV_HOME,
+        ENV_AWS_CONFIG_FILE,
+        ENV_AWS_CREDENTIALS_FILE,
+        ENV_KUBECONFIG,
+        ENV_NPMRC,
+        ENV_PIP_CONF,
+        ENV_CARGO_HOME,
+        "XDG_DATA_HOME",
+        "XDG_CACHE_HOME",
+        "XDG_STATE_HOME",
+        "XDG_CONFIG_HOME",
+    ];
+
+    pub(crate) fn with_temp_env(vars: &[(&str, &str)], f: impl FnOnce(&Path)) {
         let tmp = tempfile::TempDir::new().unwrap();
-        let saved = std::env::var_os("HOME");
+        let names: Vec<&str> = ["HOME"]
+            .into_iter()
+            .chain(OVERRIDES)
+            .chain(vars.iter().map(|(name, _)| *name))
+            .collect();
+        let saved: Vec<Option<OsString>> = names.iter().map(std::env::var_os).collect();
         std::env::set_var("HOME", tmp.path());
+        for name in OVERRIDES {
+            std::env::remove_var(name);
+        }
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
         let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(tmp.path())));
-        match saved {
-            Some(v) => std::env::set_var("HOME", v),
-            None => std::env::remove_var("HOME"),
+        for (name, value) in names.iter().zip(saved) {
+            match value {
+                Some(v) => std::env::set_var(name, v),
+                None => std::env::remove_var(name),
+            }
         }
         if let Err(payload) = result {
             std::panic::resume_unwind(payload);
@@ -167,6 +478,259 @@ mod tests {
         });
     }
 
+    // --- get_aws_credentials() ---
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_get_aws_credentials_ends_with_aws_credentials() {
+        with_temp_home(|_| {
+            let result = get_aws_credentials().unwrap();
+            assert!(Path::new(&result).ends_with(".aws/credentials"));
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_aws_files_honor_env() {
+        with_temp_env(
+            &[
+                (ENV_AWS_CONFIG_FILE, "/mnt/aws/config"),
+                (ENV_AWS_CREDENTIALS_FILE, "~/creds"),
+            ],
+            |home| {
+                assert_eq!(get_aws_config().unwrap(), OsString::from("/mnt/aws/config"));
+                assert_eq!(
+                    get_aws_credentials().unwrap(),
+                    home.join("creds").into_os_string()
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_aws_files_ignore_empty_env() {
+        with_temp_env(&[(ENV_AWS_CONFIG_FILE, "")], |home| {
+            assert_eq!(
+                get_aws_config().unwrap(),
+                home.join(".aws/config").into_os_string()
+            );
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_kubeconfig_default_and_env() {
+        with_temp_env(&[], |home| {
+            assert_eq!(
+                get_kubeconfig().unwrap(),
+                home.join(".kube/config").into_os_string()
+            );
+        });
+        with_temp_env(&[(ENV_KUBECONFIG, ":~/kube/a:/etc/kube/b")], |home| {
+            assert_eq!(
+                get_kubeconfig().unwrap(),
+                home.join("kube/a").into_os_string()
+            );
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_package_manager_configs_default() {
+        with_temp_env(&[], |home| {
+            assert_eq!(get_npmrc().unwrap(), home.join(".npmrc").into_os_string());
+            assert_eq!(
+                get_pip_conf().unwrap(),
+                home.join(".config/pip/pip.conf").into_os_string()
+            );
+            assert_eq!(
+                get_cargo_home().unwrap(),
+                home.join(".cargo").into_os_string()
+            );
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_package_manager_configs_env() {
+        with_temp_env(
+            &[
+                (ENV_NPMRC, "~/work/.npmrc"),
+                ("XDG_CONFIG_HOME", "/xdg"),
+                (ENV_CARGO_HOME, "/opt/cargo"),
+            ],
+            |home| {
+                assert_eq!(
+                    get_npmrc().unwrap(),
+                    home.join("work/.npmrc").into_os_string()
+                );
+                assert_eq!(get_pip_conf().unwrap(), OsString::from("/xdg/pip/pip.conf"));
+                assert_eq!(get_cargo_home().unwrap(), OsString::from("/opt/cargo"));
+            },
+        );
+        with_temp_env(&[(ENV_PIP_CONF, "/etc/pip.conf")], |_| {
+            assert_eq!(get_pip_conf().unwrap(), OsString::from("/etc/pip.conf"));
+        });
+    }
+
+    // --- program directories ---
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_program_files_default_to_aws_folder() {
+        with_temp_env(&[], |home| {
+            let dir = home.join(PROGRAM_FOLDER);
+            assert_eq!(get_data_file("p").unwrap(), dir.join("p").into_os_string());
+            assert_eq!(get_cache_file("c").unwrap(), dir.join("c").into_os_string());
+            assert_eq!(
+                get_log_file("l").unwrap(),
+                dir.join("logs/l").into_os_string()
+            );
+            assert_eq!(
+                get_program_config().unwrap(),
+                home.join(CONFIG_FILE).into_os_string()
+            );
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_program_files_honor_ssologinlite_home() {
+        with_temp_env(
+            &[(ENV_HOME, "/work/sso"), ("XDG_DATA_HOME", "/xdg/data")],
+            |_| {
+                assert_eq!(get_data_file("p").unwrap(), OsString::from("/work/sso/p"));
+                assert_eq!(get_cache_file("c").unwrap(), OsString::from("/work/sso/c"));
+                assert_eq!(
+                    get_log_file("l").unwrap(),
+                    OsString::from("/work/sso/logs/l")
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_program_files_follow_xdg_on_new_install() {
+        with_temp_env(
+            &[
+                ("XDG_DATA_HOME", "/xdg/data"),
+                ("XDG_CACHE_HOME", "/xdg/cache"),
+                ("XDG_STATE_HOME", "relative/is/ignored"),
+                ("XDG_CONFIG_HOME", "/xdg/config"),
+            ],
+            |home| {
+                assert_eq!(
+                    get_data_file("p").unwrap(),
+                    OsString::from("/xdg/data/ssologinlite/p")
+                );
+                assert_eq!(
+                    get_cache_file("c").unwrap(),
+                    OsString::from("/xdg/cache/ssologinlite/c")
+                );
+                assert_eq!(
+                    get_log_file("l").unwrap(),
+                    home.join(PROGRAM_FOLDER).join("logs/l").into_os_string()
+                );
+                assert_eq!(
+                    get_program_config().unwrap(),
+                    OsString::from("/xdg/config/ssologinlite")
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_path_overrides_beat_environment() {
+        with_temp_env(
+            &[
+                (ENV_HOME, "/env/home"),
+                (ENV_AWS_CONFIG_FILE, "/env/config"),
+            ],
+            |_| {
+                let overrides = PathOverrides {
+                    home: Some(PathBuf::from("/svc/home")),
+                    aws_config: Some(PathBuf::from("/svc/aws-config")),
+                    program_config: Some(PathBuf::from("/svc/ssologinlite.toml")),
+                    ..Default::default()
+                };
+                with_paths_sync(overrides, || {
+                    assert_eq!(get_data_file("p").unwrap(), OsString::from("/svc/home/p"));
+                    assert_eq!(get_aws_config().unwrap(), OsString::from("/svc/aws-config"));
+                    assert_eq!(
+                        get_program_config().unwrap(),
+                        OsString::from("/svc/ssologinlite.toml")
+                    );
+                });
+                // Outside the scope the environment applies again.
+                assert_eq!(get_data_file("p").unwrap(), OsString::from("/env/home/p"));
+                assert_eq!(get_aws_config().unwrap(), OsString::from("/env/config"));
+            },
+        );
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_program_files_keep_existing_aws_folder() {
+        with_temp_env(
+            &[
+                ("XDG_DATA_HOME", "/xdg/data"),
+                ("XDG_CACHE_HOME", "/xdg/cache"),
+            ],
+            |home| {
+                let legacy = home.join(PROGRAM_FOLDER);
+                std::fs::create_dir_all(&legacy).unwrap();
+                std::fs::write(legacy.join(PROFILES), "{}").unwrap();
+                assert_eq!(
+                    get_data_file("p").unwrap(),
+                    legacy.join("p").into_os_string()
+                );
+                assert_eq!(
+                    get_cache_file("c").unwrap(),
+                    legacy.join("c").into_os_string()
+                );
+            },
+        );
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_program_config_keeps_existing_legacy_file() {
+        let xdg = tempfile::TempDir::new().unwrap();
+        let xdg_path = xdg.path().to_str().unwrap();
+        with_temp_env(&[("XDG_CONFIG_HOME", xdg_path)], |home| {
+            let legacy = home.join(CONFIG_FILE);
+            std::fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+            std::fs::write(legacy.with_extension("toml"), "").unwrap();
+            assert_eq!(
+                get_program_config().unwrap(),
+                legacy.clone().into_os_string()
+            );
+            // Once there is an XDG config, it wins.
+            let config = xdg.path().join(PROGRAM_NAME);
+            std::fs::write(config.with_extension("yaml"), "").unwrap();
+            assert_eq!(get_program_config().unwrap(), config.into_os_string());
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_log_folder_does_not_pin_data_to_aws_folder() {
+        with_temp_env(&[("XDG_DATA_HOME", "/xdg/data")], |home| {
+            // What log4rs does at startup, with XDG_STATE_HOME unset.
+            let log_file = PathBuf::from(get_log_file("ssologinlite.log").unwrap());
+            assert!(log_file.starts_with(home.join(PROGRAM_FOLDER)));
+            std::fs::create_dir_all(log_file.parent().unwrap()).unwrap();
+            std::fs::write(&log_file, "").unwrap();
+            assert_eq!(
+                get_data_file(PROFILES).unwrap(),
+                OsString::from("/xdg/data/ssologinlite/profiles.json")
+            );
+        });
+    }
+
     // --- get_exe_path() ---
 
     #[test]
@@ -219,6 +783,87 @@ mod tests {
         assert!(restrict_file_permissions(&OsString::from(path)).is_err());
     }
 
+    // --- write_file_atomic() ---
+
+    #[test]
+    fn test_write_file_atomic_creates_file_and_parent() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("nested/dir/profiles.json");
+        write_file_atomic(&path.clone().into_os_string(), b"{}").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_write_file_atomic_sets_600() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("credentials");
+        write_file_atomic(&path.clone().into_os_string(), b"secret").unwrap();
+        let mode = metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    fn test_write_file_atomic_replaces_and_restricts_existing() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("config");
+        std::fs::write(&path, "old content that is longer").unwrap();
+        set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+        write_file_atomic(&path.clone().into_os_string(), b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        let mode = metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    fn test_write_file_atomic_leaves_no_temp_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("config");
+        write_file_atomic(&path.clone().into_os_string(), b"a").unwrap();
+        write_file_atomic(&path.into_os_string(), b"b").unwrap();
+        let names: Vec<_> = std::fs::read_dir(tmp.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![OsString::from("config")]);
+    }
+
+    #[test]
+    fn test_write_file_atomic_failure_keeps_old_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        // The target is a non-empty directory, so the rename fails.
+        let path = tmp.path().join("target");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("keep"), "x").unwrap();
+        assert!(write_file_atomic(&path.clone().into_os_string(), b"new").is_err());
+        assert!(path.join("keep").exists());
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_file_atomic_keeps_symlink() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dotfiles = tmp.path().join("dotfiles/aws-config");
+        std::fs::create_dir_all(dotfiles.parent().unwrap()).unwrap();
+        std::fs::write(&dotfiles, "old").unwrap();
+        let link = tmp.path().join("config");
+        std::os::unix::fs::symlink(&dotfiles, &link).unwrap();
+        write_file_atomic(&link.clone().into_os_string(), b"new").unwrap();
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read_to_string(&dotfiles).unwrap(), "new");
+
+        // A dangling link is written at its target too.
+        let dangling = tmp.path().join("credentials");
+        std::os::unix::fs::symlink("dotfiles/aws-credentials", &dangling).unwrap();
+        write_file_atomic(&dangling.clone().into_os_string(), b"secret").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(tmp.path().join("dotfiles/aws-credentials")).unwrap(),
+            "secret"
+        );
+    }
+
     // --- MyErrors Display ---
 
     #[test]
@@ -233,4 +878,9 @@ mod tests {
     fn test_error_display_path() {
         assert_eq!(format!("{}", MyErrors::Path), "Can not find Path");
     }
+
+    #[test]
+    fn test_error_display_write() {
+        assert_eq!(format!("{}", MyErrors::Write), "Could not write file!");
+    }
 }
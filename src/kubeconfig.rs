@@ -0,0 +1,472 @@
+use crate::aws_credentials::AWScredentials;
+use crate::config::sdk_config;
+use crate::eks::BETA_API;
+use crate::error::Error;
+use crate::file_helper::write_file_atomic;
+use anyhow::{anyhow, Result};
+use log::{debug, error, info};
+use serde_yaml::{Mapping, Value};
+use std::ffi::OsString;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EksCluster {
+    pub name: String,
+    pub arn: String,
+    pub endpoint: String,
+    pub certificate_authority: String,
+    pub region: String,
+}
+
+impl EksCluster {
+    // arn:<partition>:eks:<region>:<account>:cluster/<name>
+    pub fn account(&self) -> &str {
+        self.arn.split(':').nth(4).unwrap_or("")
+    }
+
+    // The context name: the cluster ARN unless an --alias template is given.
+    pub fn alias(&self, template: Option<&str>, profile: &str) -> String {
+        match template {
+            Some(template) => template
+                .replace("{cluster}", &self.name)
+                .replace("{region}", &self.region)
+                .replace("{profile}", profile)
+                .replace("{account}", self.account()),
+            None => self.arn.clone(),
+        }
+    }
+
+    // Looks up `cluster`, or every cluster of the region when it is None.
+    pub async fn discover(
+        credentials: &AWScredentials,
+        region: &str,
+        cluster: Option<&str>,
+    ) -> Result<Vec<EksCluster>> {
+        let client = eks_client(credentials, region)?;
+        let names = match cluster {
+            Some(cluster) => vec![cluster.to_string()],
+            None => list_clusters(&client).await?,
+        };
+        let mut clusters = Vec::new();
+        for name in names {
+            clusters.push(describe_cluster(&client, &name, region).await?);
+        }
+        Ok(clusters)
+    }
+}
+
+pub fn eks_client(credentials: &AWScredentials, region: &str) -> Result<aws_sdk_eks::Client> {
+    let config = sdk_config(Some(credentials.credentials_provider()), region)?;
+    Ok(aws_sdk_eks::Client::new(&config))
+}
+
+async fn list_clusters(client: &aws_sdk_eks::Client) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut next_token: Option<String> = None;
+    loop {
+        let output = match client
+            .list_clusters()
+            .set_next_token(next_token)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("kubeconfig.list_clusters {}", e);
+                return Err(anyhow!(Error::from_sdk(e)).context(MyErrors::ListClusters));
+            }
+        };
+        names.extend(output.clusters().iter().cloned());
+        next_token = output.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    debug!("kubeconfig.list_clusters found {:?}", names);
+    Ok(names)
+}
+
+async fn describe_cluster(
+    client: &aws_sdk_eks::Client,
+    name: &str,
+    region: &str,
+) -> Result<EksCluster> {
+    let output = match client.describe_cluster().name(name).send().await {
+        Ok(output) => output,
+        Err(e) => {
+            error!("kubeconfig.describe_cluster {}", e);
+            return Err(
+                anyhow!(Error::from_sdk(e)).context(MyErrors::DescribeCluster(name.to_string()))
+            );
+        }
+    };
+    let cluster = output.cluster();
+    let field = |value: Option<&str>| match value {
+        Some(value) => Ok(value.to_string()),
+        None => {
+            error!("kubeconfig.describe_cluster {} is not active", name);
+            Err(anyhow!(MyErrors::ClusterNotReady(name.to_string())))
+        }
+    };
+    Ok(EksCluster {
+        name: name.to_string(),
+        arn: field(cluster.and_then(|c| c.arn()))?,
+        endpoint: field(cluster.and_then(|c| c.endpoint()))?,
+        certificate_authority: field(
+            cluster
+                .and_then(|c| c.certificate_authority())
+                .and_then(|c| c.data()),
+        )?,
+        region: region.to_string(),
+    })
+}
+
+// A kubeconfig document. Only the cluster, user and context entries written
+// by ssologinlite are touched; everything else round-trips as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KubeConfig {
+    pub doc: Value,
+}
+
+impl KubeConfig {
+    pub fn from_file(file: &OsString) -> Result<KubeConfig> {
+        if !Path::new(file).exists() {
+            debug!("kubeconfig.KubeConfig.from_file {:?} does not exist", file);
+            return Ok(KubeConfig::default());
+        }
+        let data = std::fs::read_to_string(file)?;
+        KubeConfig::from_yaml(&data)
+    }
+
+    pub fn from_yaml(data: &str) -> Result<KubeConfig> {
+        let doc: Value = match serde_yaml::from_str(data) {
+            Ok(doc) => doc,
+            Err(e) => {
+                error!("kubeconfig.KubeConfig.from_yaml {}", e);
+                return Err(anyhow!(MyErrors::InvalidKubeconfig));
+            }
+        };
+        match doc {
+            // An empty file parses as null.
+            Value::Null => Ok(KubeConfig::default()),
+            Value::Mapping(_) => Ok(KubeConfig { doc }),
+            _ => Err(anyhow!(MyErrors::InvalidKubeconfig)),
+        }
+    }
+
+    pub fn to_file(&self, file: &OsString) -> Result<()> {
+        write_file_atomic(file, serde_yaml::to_string(&self.doc)?.as_bytes())
+    }
+
+    // Adds or replaces the entries for `cluster` under the context `alias`.
+    // The user runs `<exe_path> eks` with the same profile, cluster and region.
+    pub fn merge(&mut self, cluster: &EksCluster, alias: &str, profile: &str, exe_path: &str) {
+        info!("Merging {} into kubeconfig as {}", cluster.arn, alias);
+        let cluster_entry = mapping([
+            ("server", Value::from(cluster.endpoint.as_str())),
+            (
+                "certificate-authority-data",
+                Value::from(cluster.certificate_authority.as_str()),
+            ),
+        ]);
+        let exec = mapping([
+            ("apiVersion", Value::from(BETA_API)),
+            ("command", Value::from(exe_path)),
+            (
+                "args",
+                Value::Sequence(
+                    [
+                        "eks",
+                        "--profile",
+                        profile,
+                        "--cluster",
+                        &cluster.name,
+                        "--region",
+                        &cluster.region,
+                    ]
+                    .into_iter()
+                    .map(Value::from)
+                    .collect(),
+                ),
+            ),
+        ]);
+        let context_entry = mapping([
+            ("cluster", Value::from(cluster.arn.as_str())),
+            ("user", Value::from(alias)),
+        ]);
+        self.upsert("clusters", "cluster", &cluster.arn, cluster_entry);
+        self.upsert("users", "user", alias, mapping([("exec", exec)]));
+        self.upsert("contexts", "context", alias, context_entry);
+    }
+
+    pub fn current_context(&self) -> Option<&str> {
+        self.doc
+            .get("current-context")
+            .and_then(Value::as_str)
+            .filter(|context| !context.is_empty())
+    }
+
+    pub fn set_current_context(&mut self, alias: &str) {
+        self.root()
+            .insert(Value::from("current-context"), Value::from(alias));
+    }
+
+    fn root(&mut self) -> &mut Mapping {
+        if !self.doc.is_mapping() {
+            self.doc = Value::Mapping(Mapping::new());
+        }
+        match &mut self.doc {
+            Value::Mapping(root) => root,
+            _ => unreachable!(),
+        }
+    }
+
+    // Replaces the `{name, <key>: value}` item of the `list` section, or
+    // appends it.
+    fn upsert(&mut self, list: &str, key: &str, name: &str, value: Value) {
+        let section = self
+            .root()
+            .entry(Value::from(list))
+            .or_insert(Value::Sequence(Vec::new()));
+        if !section.is_sequence() {
+            *section = Value::Sequence(Vec::new());
+        }
+        let Value::Sequence(items) = section else {
+            unreachable!()
+        };
+        let item = mapping([("name", Value::from(name)), (key, value)]);
+        match items
+            .iter_mut()
+            .find(|i| i.get("name").and_then(Value::as_str) == Some(name))
+        {
+            Some(existing) => *existing = item,
+            None => items.push(item),
+        }
+    }
+}
+
+impl Default for KubeConfig {
+    fn default() -> KubeConfig {
+        KubeConfig {
+            doc: mapping([
+                ("apiVersion", Value::from("v1")),
+                ("kind", Value::from("Config")),
+                ("preferences", Value::Mapping(Mapping::new())),
+                ("clusters", Value::Sequence(Vec::new())),
+                ("users", Value::Sequence(Vec::new())),
+                ("contexts", Value::Sequence(Vec::new())),
+            ]),
+        }
+    }
+}
+
+fn mapping<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Mapping(
+        entries
+            .into_iter()
+            .map(|(key, value)| (Value::from(key), value))
+            .collect(),
+    )
+}
+
+#[derive(Debug)]
+enum MyErrors {
+    ListClusters,
+    DescribeCluster(String),
+    ClusterNotReady(String),
+    InvalidKubeconfig,
+}
+
+impl std::fmt::Display for MyErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ListClusters => write!(f, "Could not list EKS clusters!"),
+            Self::DescribeCluster(name) => write!(f, "Could not describe EKS cluster {}!", name),
+            Self::ClusterNotReady(name) => {
+                write!(f, "EKS cluster {} has no endpoint or certificate yet", name)
+            }
+            Self::InvalidKubeconfig => write!(f, "kubeconfig is not a valid YAML mapping"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_cluster(name: &str) -> EksCluster {
+        EksCluster {
+            name: name.to_string(),
+            arn: format!("arn:aws:eks:eu-west-1:123456789012:cluster/{}", name),
+            endpoint: format!("https://{}.eks.amazonaws.com", name),
+            certificate_authority: "Q0VSVA==".to_string(),
+            region: "eu-west-1".to_string(),
+        }
+    }
+
+    fn names(config: &KubeConfig, list: &str) -> Vec<String> {
+        config.doc[list]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|i| i["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_account_from_arn() {
+        assert_eq!(make_cluster("main").account(), "123456789012");
+    }
+
+    #[test]
+    fn test_alias_defaults_to_arn() {
+        let cluster = make_cluster("main");
+        assert_eq!(cluster.alias(None, "prod"), cluster.arn);
+    }
+
+    #[test]
+    fn test_alias_template() {
+        let cluster = make_cluster("main");
+        assert_eq!(
+            cluster.alias(Some("{profile}/{account}/{region}/{cluster}"), "prod"),
+            "prod/123456789012/eu-west-1/main"
+        );
+        assert_eq!(cluster.alias(Some("static"), "prod"), "static");
+    }
+
+    #[test]
+    fn test_merge_into_empty_config() {
+        let cluster = make_cluster("main");
+        let mut config = KubeConfig::default();
+        config.merge(&cluster, "prod-main", "prod", "/usr/local/bin/ssologinlite");
+
+        assert_eq!(names(&config, "clusters"), vec![cluster.arn.clone()]);
+        assert_eq!(names(&config, "users"), vec!["prod-main"]);
+        assert_eq!(names(&config, "contexts"), vec!["prod-main"]);
+        let cluster_entry = &config.doc["clusters"][0]["cluster"];
+        assert_eq!(
+            cluster_entry["server"],
+            Value::from(cluster.endpoint.as_str())
+        );
+        assert_eq!(
+            cluster_entry["certificate-authority-data"],
+            Value::from("Q0VSVA==")
+        );
+        let exec = &config.doc["users"][0]["user"]["exec"];
+        assert_eq!(exec["apiVersion"], Value::from(BETA_API));
+        assert_eq!(exec["command"], Value::from("/usr/local/bin/ssologinlite"));
+        let args: Vec<&str> = exec["args"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|a| a.as_str().unwrap())
+            .collect();
+        assert_eq!(
+            args,
+            vec![
+                "eks",
+                "--profile",
+                "prod",
+                "--cluster",
+                "main",
+                "--region",
+                "eu-west-1"
+            ]
+        );
+        let context = &config.doc["contexts"][0]["context"];
+        assert_eq!(context["cluster"], Value::from(cluster.arn.as_str()));
+        assert_eq!(context["user"], Value::from("prod-main"));
+        assert_eq!(config.current_context(), None);
+    }
+
+    #[test]
+    fn test_merge_is_idempotent() {
+        let cluster = make_cluster("main");
+        let mut config = KubeConfig::default();
+        config.merge(&cluster, "main", "prod", "ssologinlite");
+        let once = config.clone();
+        config.merge(&cluster, "main", "prod", "ssologinlite");
+        assert_eq!(config, once);
+    }
+
+    #[test]
+    fn test_merge_keeps_unrelated_entries_and_updates_existing() {
+        let mut config = KubeConfig::from_yaml(
+            r#"apiVersion: v1
+kind: Config
+current-context: kind
+clusters:
+- name: kind
+  cluster:
+    server: https://127.0.0.1:6443
+- name: arn:aws:eks:eu-west-1:123456789012:cluster/main
+  cluster:
+    server: https://old.example.com
+users:
+- name: kind
+  user:
+    token: abc
+contexts:
+- name: kind
+  context:
+    cluster: kind
+    user: kind
+"#,
+        )
+        .unwrap();
+        let cluster = make_cluster("main");
+        config.merge(&cluster, "main", "prod", "ssologinlite");
+
+        assert_eq!(
+            names(&config, "clusters"),
+            vec!["kind", cluster.arn.as_str()]
+        );
+        assert_eq!(names(&config, "users"), vec!["kind", "main"]);
+        assert_eq!(names(&config, "contexts"), vec!["kind", "main"]);
+        assert_eq!(
+            config.doc["clusters"][1]["cluster"]["server"],
+            Value::from(cluster.endpoint.as_str())
+        );
+        assert_eq!(config.doc["users"][0]["user"]["token"], Value::from("abc"));
+        assert_eq!(config.current_context(), Some("kind"));
+    }
+
+    #[test]
+    fn test_set_current_context() {
+        let mut config = KubeConfig::default();
+        config.set_current_context("main");
+        assert_eq!(config.current_context(), Some("main"));
+    }
+
+    #[test]
+    fn test_from_yaml_empty_and_invalid() {
+        assert_eq!(KubeConfig::from_yaml("").unwrap(), KubeConfig::default());
+        assert!(KubeConfig::from_yaml("- a\n- b\n").is_err());
+        assert!(KubeConfig::from_yaml("clusters: [").is_err());
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let file = tmp.path().join("nested/config").into_os_string();
+        assert_eq!(KubeConfig::from_file(&file).unwrap(), KubeConfig::default());
+
+        let mut config = KubeConfig::default();
+        config.merge(&make_cluster("main"), "main", "prod", "ssologinlite");
+        config.set_current_context("main");
+        config.to_file(&file).unwrap();
+        assert_eq!(KubeConfig::from_file(&file).unwrap(), config);
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            MyErrors::DescribeCluster("main".to_string()).to_string(),
+            "Could not describe EKS cluster main!"
+        );
+        assert_eq!(
+            MyErrors::InvalidKubeconfig.to_string(),
+            "kubeconfig is not a valid YAML mapping"
+        );
+    }
+}
@@ -1,13 +1,49 @@
+// The library surface: Client and its CredentialResolver, the Error they
+// return, and presigning.
+pub mod client;
+pub mod error;
+pub mod presign;
+
+pub use client::{Client, CredentialResolver};
+pub use error::Error;
+
+// Used by the ssologinlite binary, not a stable API.
+#[doc(hidden)]
 pub mod aws_credentials;
+#[doc(hidden)]
 pub mod aws_profile;
+#[doc(hidden)]
 pub mod aws_sso_credentials;
-pub mod aws_sso_registration;
-pub mod cache;
+#[doc(hidden)]
+pub mod codeartifact;
+#[doc(hidden)]
 pub mod config;
-pub mod constants;
+#[doc(hidden)]
+pub mod ecr;
+#[doc(hidden)]
 pub mod eks;
+#[doc(hidden)]
+pub mod eks_doctor;
+#[doc(hidden)]
 pub mod file_helper;
+#[doc(hidden)]
+pub mod kubeconfig;
+#[doc(hidden)]
 pub mod logger;
-pub mod mywebbrowser;
+#[doc(hidden)]
 pub mod parser;
+#[doc(hidden)]
+pub mod profile_sync;
+#[doc(hidden)]
+pub mod rds;
+#[doc(hidden)]
+pub mod s3;
+#[doc(hidden)]
 pub mod tui;
+
+pub(crate) mod aws_sso_registration;
+pub(crate) mod cache;
+pub(crate) mod config_plan;
+pub(crate) mod constants;
+pub(crate) mod http_client;
+pub(crate) mod mywebbrowser;
@@ -1,5 +1,4 @@
-use crate::constants::PROGRAM_FOLDER;
-use crate::file_helper::get_home_os_string;
+use crate::file_helper::get_log_file;
 use anyhow::{anyhow, Result};
 use log::error;
 use log::LevelFilter;
@@ -19,9 +18,8 @@ pub fn logger(level: &str) -> Result<()> {
         _ => LevelFilter::Info,
     };
 
-    let log_file = get_home_os_string(format!("{PROGRAM_FOLDER}/logs/ssologinlite.log",).as_str())?;
-    let log_file_pattern_str =
-        get_home_os_string(format!("{PROGRAM_FOLDER}/logs/ssologinlite_{{}}.log",).as_str())?;
+    let log_file = get_log_file("ssologinlite.log")?;
+    let log_file_pattern_str = get_log_file("ssologinlite_{}.log")?;
     let log_file_pattern = match log_file_pattern_str.as_os_str().to_str() {
         Some(s) => s,
         None => {
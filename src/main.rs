@@ -3,19 +3,36 @@ use anyhow::{anyhow, Result};
 // use aws_config::imds::credentials;
 use clap::Parser;
 use log::{debug, error};
-use ssologinlite::aws_profile::{Profile::AssumeSsoProfile, Profile::SsoProfile, Profiles};
-use ssologinlite::aws_sso_credentials::SsoCredentials;
+use ssologinlite::aws_credentials::{AWScredentials, RoleToAssume};
+use ssologinlite::aws_profile::{
+    Profile, Profile::AssumeSsoProfile, Profile::OtherProfile, Profile::SsoProfile, Profiles,
+};
+use ssologinlite::aws_sso_credentials::{disable_browser_login, SsoCredentials};
+use ssologinlite::codeartifact::{
+    repository_endpoint, CodeArtifactDomain, CodeArtifactToken, PackageSettings,
+};
 use ssologinlite::config::ProgramConfig;
-use ssologinlite::eks::EksToken;
+use ssologinlite::ecr::{DockerCredentialHelper, CREDENTIALS_NOT_FOUND};
+use ssologinlite::eks::{EksToken, ExecInfo};
+use ssologinlite::eks_doctor::DoctorReport;
+use ssologinlite::error::{exit_code, report, with_hint, Error, Hint};
+use ssologinlite::file_helper::{get_exe_path, get_kubeconfig};
+use ssologinlite::kubeconfig::{EksCluster, KubeConfig};
 use ssologinlite::logger::logger;
-use ssologinlite::parser::{Cli, Commands};
+use ssologinlite::parser::{
+    resolve_invocation, Cli, Commands, DockerCredentialAction, EksArgs, EksCommands,
+};
+use ssologinlite::profile_sync::SyncPlan;
+use ssologinlite::rds::{region_from_host, DbEndpoint};
+use ssologinlite::s3::{bucket_region, session_cutoff, S3Object};
 use ssologinlite::tui;
+use std::io::Read;
 use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<ExitCode> {
+async fn main() -> ExitCode {
     // Parse command-line arguments
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(resolve_invocation(std::env::args_os()));
 
     // Set up logging based on debug flag
     if cli.debug {
@@ -24,11 +41,38 @@ async fn main() -> Result<ExitCode> {
         let _ = logger("info");
     };
 
+    match run(&cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            error!("main {:?}", e);
+            // Without --debug the log is the only other place the cause
+            // shows up, so stderr gets it and what to do about it.
+            eprint!("{}", report(&e));
+            // The codes are listed in the README, see error.rs.
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<ExitCode> {
     // Match on the command provided
     match &cli.command {
-        Commands::Setup => {
+        Commands::Setup(args) => {
             debug!("Setting up profiles");
-            Profiles::setup_file()?;
+            if args.dry_run {
+                let (_, plan) = Profiles::plan_setup()?;
+                print!("{}", plan.diff()?);
+            } else {
+                Profiles::setup_file()?;
+            }
+        }
+        Commands::Unsetup(args) => {
+            debug!("Restoring native profiles");
+            if args.dry_run {
+                print!("{}", Profiles::plan_unsetup()?.diff()?);
+            } else {
+                Profiles::unsetup_file()?;
+            }
         }
         Commands::Token(args) => {
             debug!("Getting creds for {:?}", args.profile);
@@ -47,52 +91,213 @@ async fn main() -> Result<ExitCode> {
                 }
                 _ => {
                     error!("Profile not found");
-                    return Err(anyhow!(MyErrors::ProfileNotFoundError));
+                    return Err(anyhow!(Error::ProfileNotFound(args.profile.clone())));
+                }
+            }
+        }
+        Commands::Eks(EksArgs {
+            command: Some(EksCommands::Kubeconfig(args)),
+            ..
+        }) => {
+            let (credentials, profile_region) = profile_credentials(&args.profile).await?;
+            let region = resolve_region(&args.region, profile_region)?;
+            let clusters =
+                EksCluster::discover(&credentials, &region, args.cluster.as_deref()).await?;
+            let file = match &args.kubeconfig {
+                Some(path) => path.clone().into_os_string(),
+                None => get_kubeconfig()?,
+            };
+            let exe_path = get_exe_path()?;
+            let exe_path = exe_path.to_string_lossy();
+            let mut config = KubeConfig::from_file(&file)?;
+            let mut last_alias = None;
+            for cluster in clusters.iter() {
+                let alias = cluster.alias(args.alias.as_deref(), &args.profile);
+                config.merge(cluster, &alias, &args.profile, &exe_path);
+                println!("Added {} as context {}", cluster.arn, alias);
+                last_alias = Some(alias);
+            }
+            // A single cluster becomes the current context, like
+            // `aws eks update-kubeconfig`; --all only fills an unset one.
+            if let Some(alias) = last_alias {
+                if args.cluster.is_some() || config.current_context().is_none() {
+                    config.set_current_context(&alias);
                 }
             }
+            config.to_file(&file)?;
+        }
+        Commands::Eks(EksArgs {
+            command: Some(EksCommands::Doctor(args)),
+            ..
+        }) => {
+            let target = EksTarget::resolve(
+                args.profile.as_deref(),
+                args.context.as_deref(),
+                args.cluster.as_deref(),
+                &args.region,
+            )?;
+            let role = args.role_arn.as_ref().map(|role_arn| RoleToAssume {
+                role_arn: role_arn.clone(),
+                external_id: args.external_id.clone(),
+            });
+            let report = DoctorReport::run(
+                &target.profile,
+                &target.profile_name,
+                &target.region,
+                &target.cluster,
+                args.sts_endpoint.as_deref(),
+                role.as_ref(),
+            )
+            .await;
+            print!("{}", report);
+            if !report.passed() {
+                return Ok(ExitCode::from(1));
+            }
         }
         Commands::Eks(args) => {
-            debug!("Getting creds for {:?}", args.profile);
+            let EksTarget {
+                profile_name,
+                profile,
+                cluster,
+                region,
+            } = EksTarget::resolve(
+                args.profile.as_deref(),
+                args.context.as_deref(),
+                args.cluster.as_deref(),
+                &args.region,
+            )?;
+            let exec_info = ExecInfo::from_env()?;
+            if !exec_info.interactive() {
+                disable_browser_login();
+            }
+            let role = args.role_arn.as_ref().map(|role_arn| RoleToAssume {
+                role_arn: role_arn.clone(),
+                external_id: args.external_id.clone(),
+            });
+            let eks_token = EksToken::get(
+                &profile,
+                &profile_name,
+                region,
+                &cluster,
+                &exec_info,
+                args.sts_endpoint.as_deref(),
+                role.as_ref(),
+            )
+            .await?;
+            println!("{}", eks_token);
+        }
+        Commands::RdsToken(args) => {
+            let (credentials, profile_region) = profile_credentials(&args.profile).await?;
+            let region = resolve_region(
+                &args.region,
+                region_from_host(&args.host).or(profile_region),
+            )?;
+            let endpoint = DbEndpoint {
+                host: args.host.clone(),
+                port: args.port,
+                user: args.user.clone(),
+                region,
+            };
+            let token = endpoint.auth_token(&credentials)?;
+            println!(
+                "{}",
+                endpoint.connection_string(args.format, args.dbname.as_deref(), &token)
+            );
+        }
+        Commands::Presign(args) => {
+            let object = S3Object::parse(&args.uri)?;
+            let (credentials, profile_region) = profile_credentials(&args.profile).await?;
+            let region = match &args.region {
+                Some(region) => region.clone(),
+                None => bucket_region(&object.bucket, profile_region.as_deref()).await?,
+            };
+            if let Some(cutoff) = session_cutoff(&credentials, args.expires) {
+                eprintln!(
+                    "The URL stops working at {}, when the profile's session ends",
+                    cutoff.to_rfc3339()
+                );
+            }
+            println!(
+                "{}",
+                object.presign(
+                    &credentials,
+                    &region,
+                    args.addressing,
+                    args.method,
+                    args.expires
+                )?
+            );
+        }
+        Commands::Codeartifact(args) => {
             let profile = Profiles::get_profile(args.profile.clone())?;
-            debug!("Profile {:?}", profile);
-
-            // Match on the profile type and get the token
-            let (credentials, profile_region) = match profile {
-                SsoProfile(profile) => (profile.get_credentials().await?, profile.region),
-                AssumeSsoProfile(profile) => {
-                    (profile.get_credentials().await?, Some(profile.region))
-                }
-                _ => {
-                    error!("Profile not found");
-                    return Err(anyhow!(MyErrors::ProfileNotFoundError));
-                }
+            if let OtherProfile = profile {
+                error!("Profile not found");
+                return Err(anyhow!(Error::ProfileNotFound(args.profile.clone())));
+            }
+            let domain = CodeArtifactDomain {
+                domain: args.domain.clone(),
+                domain_owner: args.domain_owner.clone(),
+                region: resolve_region(&args.region, profile.region())?,
             };
-            let region = match args.region.clone() {
-                Some(region) => region,
-                None => match profile_region {
-                    Some(region) => region,
+            let token = CodeArtifactToken::get(&profile, &args.profile, &domain).await?;
+            match (&args.repository, args.tool) {
+                (Some(repository), Some(tool)) => {
+                    let credentials = profile.get_credentials().await?;
+                    let settings = PackageSettings {
+                        tool,
+                        name: format!("{}-{}", domain.domain, repository),
+                        endpoint: repository_endpoint(&credentials, &domain, repository, tool)
+                            .await?,
+                        token: token.token,
+                    };
+                    if args.write {
+                        for file in settings.write()? {
+                            println!("Wrote {}", file.to_string_lossy());
+                        }
+                    } else {
+                        print!("{}", settings.render());
+                    }
+                }
+                _ => println!("{}", token.token),
+            }
+        }
+        Commands::DockerCredential(args) => {
+            debug!("docker-credential {:?}", args.action);
+            let helper = DockerCredentialHelper::new(&ProgramConfig::new()?);
+            // get, store and erase read their request from stdin, list has
+            // none.
+            let mut input = String::new();
+            if args.action != DockerCredentialAction::List {
+                std::io::stdin().read_to_string(&mut input)?;
+            }
+            match args.action {
+                DockerCredentialAction::Get => match helper.get(input.trim()).await? {
+                    Some(credentials) => println!("{}", serde_json::to_string(&credentials)?),
                     None => {
-                        error!("Region not found");
-                        return Err(anyhow!(MyErrors::RegionNotFoundError));
+                        println!("{}", CREDENTIALS_NOT_FOUND);
+                        return Ok(ExitCode::from(1));
                     }
                 },
-            };
-            let cluster = match args.cluster.clone() {
-                Some(cluster) => cluster,
-                None => {
-                    error!("Cluster not found");
-                    return Err(anyhow!("no cluster argument"));
+                DockerCredentialAction::List => {
+                    println!("{}", serde_json::to_string(&helper.list())?)
                 }
-            };
-            let eks_token = EksToken::from_credentials(credentials, region, &cluster)?;
-            println!("{}", eks_token);
+                // ECR tokens are minted on each get, there is nothing to keep.
+                DockerCredentialAction::Store | DockerCredentialAction::Erase => {}
+            }
         }
         Commands::SSOExpiration => {
             let conf = ProgramConfig::new()?;
             let credentials = match conf.default_sso_url {
                 Some(url) => SsoCredentials::from_url(url.as_str()).await?,
                 None => {
-                    return Err(anyhow!(MyErrors::NoDefaultError));
+                    return Err(with_hint(
+                        anyhow!(Error::MissingSetting(
+                            "No default SSO URL found".to_string()
+                        )),
+                        Some(Hint(
+                            "set default_sso_url in the ssologinlite config".to_string(),
+                        )),
+                    ));
                 }
             };
 
@@ -111,12 +316,67 @@ async fn main() -> Result<ExitCode> {
         Commands::Tui => {
             tui::run()?;
         }
+        Commands::WriteCredentials(args) => {
+            let profiles = Profiles::from_file()?;
+            let mut names: Vec<String> = match &args.profile {
+                Some(profile) => vec![profile.clone()],
+                None => profiles
+                    .profiles
+                    .iter()
+                    .filter(|(_, p)| matches!(p, SsoProfile(_) | AssumeSsoProfile(_)))
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+            };
+            names.sort();
+            let mut credentials: Vec<(String, AWScredentials)> = Vec::new();
+            for name in names {
+                debug!("Getting creds for {:?}", name);
+                let profile = match profiles.profiles.get(&name) {
+                    Some(profile) => profile,
+                    None => {
+                        error!("Profile not found");
+                        return Err(anyhow!(Error::ProfileNotFound(name.clone())));
+                    }
+                };
+                credentials.push((name, profile.get_credentials().await?));
+            }
+            AWScredentials::write_credentials_file(&credentials)?;
+        }
+        Commands::Sync(args) => {
+            let (plan, file, config) = SyncPlan::load()?;
+            let plan = plan.resolve(args.prefer, &args.profile);
+            if plan.entries.is_empty() {
+                println!("profiles.json and ~/.aws/config are in sync");
+                return Ok(ExitCode::from(0));
+            }
+            for entry in plan.entries.iter() {
+                println!("{}", entry);
+            }
+            let config = plan.apply(file, config, args.dry_run)?;
+            if args.dry_run {
+                print!("{}", config.diff()?);
+            }
+            if plan.conflicts() > 0 {
+                eprintln!(
+                    "{} conflict(s) skipped; re-run with --prefer config or --prefer file",
+                    plan.conflicts()
+                );
+                return Ok(ExitCode::from(1));
+            }
+        }
         Commands::SSOExpiresSoon => {
             let conf = ProgramConfig::new()?;
             let credentials = match conf.default_sso_url {
                 Some(url) => SsoCredentials::from_url(url.as_str()).await?,
                 None => {
-                    return Err(anyhow!(MyErrors::NoDefaultError));
+                    return Err(with_hint(
+                        anyhow!(Error::MissingSetting(
+                            "No default SSO URL found".to_string()
+                        )),
+                        Some(Hint(
+                            "set default_sso_url in the ssologinlite config".to_string(),
+                        )),
+                    ));
                 }
             };
             let (expires_in, _) = credentials.expires()?;
@@ -134,23 +394,91 @@ async fn main() -> Result<ExitCode> {
     Ok(ExitCode::from(0))
 }
 
-// Custom error enum. The shared "Error" suffix is intentional and matches
-// the pattern used by sibling modules' MyErrors enums.
-#[derive(Debug)]
-#[allow(clippy::enum_variant_names)]
-enum MyErrors {
-    RegionNotFoundError,
-    ProfileNotFoundError,
-    NoDefaultError,
+// Credentials of an SSO or assume-role profile, with the profile region.
+async fn profile_credentials(profile_name: &str) -> Result<(AWScredentials, Option<String>)> {
+    debug!("Getting creds for {:?}", profile_name);
+    let profile = Profiles::get_profile(profile_name.to_string())?;
+    debug!("Profile {:?}", profile);
+
+    match profile {
+        OtherProfile => {
+            error!("Profile not found");
+            Err(anyhow!(Error::ProfileNotFound(profile_name.to_string())))
+        }
+        _ => Ok((profile.get_credentials().await?, profile.region())),
+    }
 }
 
-// Implement Display trait for custom error
-impl std::fmt::Display for MyErrors {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::RegionNotFoundError => write!(f, "Region not found!"),
-            Self::ProfileNotFoundError => write!(f, "Profile not found!"),
-            Self::NoDefaultError => write!(f, "No default SSO URL found"),
+// The --region argument, or else the profile region.
+fn resolve_region(arg: &Option<String>, profile_region: Option<String>) -> Result<String> {
+    match arg.clone().or(profile_region) {
+        Some(region) => Ok(region),
+        None => {
+            error!("Region not found");
+            Err(with_hint(
+                anyhow!(Error::MissingSetting("Region not found!".to_string())),
+                Some(Hint(
+                    "pass --region or set a region for the profile".to_string(),
+                )),
+            ))
         }
     }
 }
+
+// The profile, cluster and region `eks` and `eks doctor` work on.
+struct EksTarget {
+    profile_name: String,
+    profile: Profile,
+    cluster: String,
+    region: String,
+}
+
+impl EksTarget {
+    // --context picks the profile and cluster from the profiles' EKS
+    // clusters. Otherwise --cluster, or the profile's first EKS cluster, in
+    // the region configured for it unless --region is given. clap requires
+    // --profile or --context.
+    fn resolve(
+        profile: Option<&str>,
+        context: Option<&str>,
+        cluster: Option<&str>,
+        region: &Option<String>,
+    ) -> Result<EksTarget> {
+        let (profile_name, default) = match context {
+            Some(context) => {
+                let (name, cluster) = Profiles::from_file()?.find_eks_context(context)?;
+                (name, Some(cluster))
+            }
+            None => (profile.unwrap_or_default().to_string(), None),
+        };
+        debug!("Resolving EKS cluster for {:?}", profile_name);
+        let profile = Profiles::get_profile(profile_name.clone())?;
+        if let OtherProfile = profile {
+            error!("Profile not found");
+            return Err(anyhow!(Error::ProfileNotFound(profile_name.clone())));
+        }
+        let default = default.or_else(|| profile.eks_cluster(cluster).cloned());
+        let cluster = match (cluster, &default) {
+            (Some(cluster), _) => cluster.to_string(),
+            (None, Some(default)) => default.cluster.clone(),
+            (None, None) => {
+                error!("Cluster not found");
+                return Err(with_hint(
+                    anyhow!(Error::MissingSetting(format!(
+                        "No --cluster given and profile {} has no EKS clusters",
+                        profile_name
+                    ))),
+                    Some(Hint("pass --cluster".to_string())),
+                ));
+            }
+        };
+        let cluster_region = default.and_then(|default| default.region);
+        let region = resolve_region(region, cluster_region.or(profile.region()))?;
+        Ok(EksTarget {
+            profile_name,
+            profile,
+            cluster,
+            region,
+        })
+    }
+}
@@ -1,4 +1,11 @@
+use crate::codeartifact::PackageTool;
+use crate::constants::DOCKER_CREDENTIAL_HELPER;
+use crate::profile_sync::Side;
+use crate::rds::ConnectionFormat;
+use crate::s3::{parse_expires, Addressing, HttpMethod};
 use clap::{Args, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::Path;
 // use clap_builder::derive::Parser;
 /// Oidc helper for aws sso login
 /// sets itself up in the aws config file as credential_process
@@ -15,14 +22,33 @@ pub struct Cli {
     pub command: Commands,
 }
 
+// Docker runs `docker-credential-<name> <action>`; installed under that name
+// (e.g. a symlink), the binary turns it into `docker-credential <action>`.
+pub fn resolve_invocation<I: IntoIterator<Item = OsString>>(args: I) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    let helper = args
+        .first()
+        .and_then(|exe| Path::new(exe).file_stem())
+        .is_some_and(|stem| stem == DOCKER_CREDENTIAL_HELPER);
+    if helper {
+        args.insert(1, OsString::from("docker-credential"));
+    }
+    args
+}
+
+// Parsed once per run, so the size of EksArgs doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
     /// Setup config ~/.aws/config file.
     /// Will back up current config file.
-    Setup,
+    Setup(DryRunArgs),
+    /// Restore native SSO settings in ~/.aws/config.
+    /// Will back up current config file.
+    Unsetup(DryRunArgs),
     /// Get a auth token for a profile
     Token(TokenArgs),
-    /// Gets EKS auth token.
+    /// Gets EKS auth token, or writes kubeconfig entries.
     Eks(EksArgs),
     /// Time left before the next sso login.
     SSOExpiration,
@@ -30,40 +56,303 @@ pub enum Commands {
     SSOExpiresSoon,
     /// Browse / add profiles in an interactive TUI.
     Tui,
+    /// Write static credentials to ~/.aws/credentials.
+    /// For tools that do not support credential_process.
+    WriteCredentials(WriteCredentialsArgs),
+    /// Re-sync ~/.aws/config and profiles.json.
+    /// Reports added, removed and changed profiles, then applies them.
+    Sync(SyncArgs),
+    /// Generate an RDS / Aurora IAM database auth token.
+    RdsToken(RdsTokenArgs),
+    /// Presign an S3 object URL, e.g. to share it.
+    Presign(PresignArgs),
+    /// Get a CodeArtifact token, or package manager settings using it.
+    Codeartifact(CodeartifactArgs),
+    /// Docker credential helper for ECR registries.
+    /// Also runs when the binary is called docker-credential-ssologinlite.
+    DockerCredential(DockerCredentialArgs),
+}
+
+#[derive(Args)]
+pub struct RdsTokenArgs {
+    /// Profile to sign the token with, defaults to $AWS_PROFILE
+    #[arg(short('p'), long, env = "AWS_PROFILE")]
+    pub profile: String,
+    /// Database endpoint
+    #[arg(long)]
+    pub host: String,
+    /// Database port
+    #[arg(long)]
+    pub port: u16,
+    /// Database user
+    #[arg(short('u'), long)]
+    pub user: String,
+    /// Region of the database, defaults to the one in --host, then the
+    /// profile region
+    #[arg(short('r'), long)]
+    pub region: Option<String>,
+    /// Print the token, or a psql / mysql command using it
+    #[arg(long, value_enum, default_value_t = ConnectionFormat::Token)]
+    pub format: ConnectionFormat,
+    /// Database name for the psql / mysql command
+    #[arg(long)]
+    pub dbname: Option<String>,
+}
+
+#[derive(Args)]
+pub struct PresignArgs {
+    /// Object to presign, s3://bucket/key
+    pub uri: String,
+    /// Profile to sign the URL with, defaults to $AWS_PROFILE
+    #[arg(short('p'), long, env = "AWS_PROFILE")]
+    pub profile: String,
+    /// How long the URL is valid: seconds, or a number of s, m, h or d
+    #[arg(short('e'), long, default_value = "1h", value_parser = parse_expires)]
+    pub expires: u64,
+    /// HTTP method the URL allows
+    #[arg(short('m'), long, value_enum, default_value_t = HttpMethod::Get)]
+    pub method: HttpMethod,
+    /// Region of the bucket, looked up from S3 when not given
+    #[arg(short('r'), long)]
+    pub region: Option<String>,
+    /// Virtual-hosted (bucket.s3.region...) or path-style (s3.region.../bucket) URLs
+    #[arg(long, value_enum, default_value_t = Addressing::Auto)]
+    pub addressing: Addressing,
+}
+
+#[derive(Args)]
+pub struct CodeartifactArgs {
+    /// Profile to get the token with, defaults to $AWS_PROFILE
+    #[arg(short('p'), long, env = "AWS_PROFILE")]
+    pub profile: String,
+    /// CodeArtifact domain
+    #[arg(short('d'), long)]
+    pub domain: String,
+    /// Account owning the domain, defaults to the profile's
+    #[arg(long)]
+    pub domain_owner: Option<String>,
+    /// Region of the domain, defaults to the profile region
+    #[arg(short('r'), long)]
+    pub region: Option<String>,
+    /// Repository the package manager settings point at
+    #[arg(long)]
+    pub repository: Option<String>,
+    /// Print settings for this package manager instead of the bare token
+    #[arg(long, value_enum, requires = "repository")]
+    pub tool: Option<PackageTool>,
+    /// Merge the settings into the package manager's user config
+    #[arg(long, requires = "tool")]
+    pub write: bool,
+}
+
+#[derive(Args)]
+pub struct DockerCredentialArgs {
+    #[command(subcommand)]
+    pub action: DockerCredentialAction,
+}
+
+// The docker credential helper protocol: the request is read from stdin.
+#[derive(Subcommand, Clone, Copy, Debug, PartialEq)]
+pub enum DockerCredentialAction {
+    /// Print the credentials of the registry read from stdin
+    Get,
+    /// Print the registries mapped to profiles
+    List,
+    /// Accepted and ignored, ECR tokens are minted on each get
+    Store,
+    /// Accepted and ignored, ECR tokens are minted on each get
+    Erase,
+}
+
+#[derive(Args)]
+pub struct DryRunArgs {
+    /// Print the changes as a unified diff instead of writing them
+    #[arg(long)]
+    pub dry_run: bool,
 }
 
 #[derive(Args)]
 pub struct TokenArgs {
-    /// Account Number
-    #[arg(short('p'), long)]
+    /// Profile name, defaults to $AWS_PROFILE
+    #[arg(short('p'), long, env = "AWS_PROFILE")]
     pub profile: String,
 }
 
+// Profile is an Option so `eks kubeconfig` can run without it; clap still
+// requires it (or --context) when no subcommand is given.
 #[derive(Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct EksArgs {
-    /// Profile to use for EKS
-    #[arg(short('p'), long)]
+    /// Profile to use for EKS, defaults to $AWS_PROFILE
+    #[arg(
+        short('p'),
+        long,
+        env = "AWS_PROFILE",
+        required_unless_present = "context"
+    )]
+    pub profile: Option<String>,
+    /// Region of the cluster, defaults to the cluster's configured region,
+    /// then the profile region
+    #[arg(short('r'), long)]
+    pub region: Option<String>,
+    /// Cluster name, defaults to the profile's first EKS cluster
+    #[arg(short('c'), long)]
+    pub cluster: Option<String>,
+    /// EKS cluster alias from the profiles, picks the profile, cluster and
+    /// region. Takes precedence over --profile
+    #[arg(long)]
+    pub context: Option<String>,
+    /// IAM role to assume from the profile credentials before signing the
+    /// token, e.g. the role the cluster maps in aws-auth
+    #[arg(long)]
+    pub role_arn: Option<String>,
+    /// External ID to pass when assuming --role-arn
+    #[arg(long, requires = "role_arn")]
+    pub external_id: Option<String>,
+    /// STS host to sign the token for, e.g. sts.amazonaws.com or a FIPS or
+    /// VPC endpoint. Defaults to the regional endpoint of the partition
+    #[arg(long)]
+    pub sts_endpoint: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<EksCommands>,
+}
+
+#[derive(Subcommand)]
+pub enum EksCommands {
+    /// Add clusters to ~/.kube/config, authenticating through this binary.
+    Kubeconfig(KubeconfigArgs),
+    /// Check each step of EKS authentication and report the one that fails.
+    Doctor(DoctorArgs),
+}
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// Profile to check, defaults to $AWS_PROFILE
+    #[arg(
+        short('p'),
+        long,
+        env = "AWS_PROFILE",
+        required_unless_present = "context"
+    )]
+    pub profile: Option<String>,
+    /// Region of the cluster, as with `eks`
+    #[arg(short('r'), long)]
+    pub region: Option<String>,
+    /// Cluster name, defaults to the profile's first EKS cluster
+    #[arg(short('c'), long)]
+    pub cluster: Option<String>,
+    /// EKS cluster alias from the profiles, as with `eks`
+    #[arg(long)]
+    pub context: Option<String>,
+    /// IAM role assumed from the profile credentials, as with `eks`
+    #[arg(long)]
+    pub role_arn: Option<String>,
+    /// External ID to pass when assuming --role-arn
+    #[arg(long, requires = "role_arn")]
+    pub external_id: Option<String>,
+    /// STS host to sign the token for, as with `eks`
+    #[arg(long)]
+    pub sts_endpoint: Option<String>,
+}
+
+#[derive(Args)]
+pub struct KubeconfigArgs {
+    /// Profile to use for EKS, defaults to $AWS_PROFILE
+    #[arg(short('p'), long, env = "AWS_PROFILE")]
     pub profile: String,
-    /// Region of the cluster
+    /// Region of the clusters, defaults to the profile region
     #[arg(short('r'), long)]
     pub region: Option<String>,
     /// Cluster name
-    #[arg(short('c'), long)]
+    #[arg(
+        short('c'),
+        long,
+        required_unless_present = "all",
+        conflicts_with = "all"
+    )]
     pub cluster: Option<String>,
+    /// Add every cluster of the region instead of --cluster
+    #[arg(short('a'), long)]
+    pub all: bool,
+    /// Context name, with {cluster}, {region}, {profile} and {account}
+    /// placeholders. Defaults to the cluster ARN
+    #[arg(long)]
+    pub alias: Option<String>,
+    /// Kubeconfig to update, defaults to $KUBECONFIG or ~/.kube/config
+    #[arg(long)]
+    pub kubeconfig: Option<std::path::PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WriteCredentialsArgs {
+    /// Profile to write
+    #[arg(
+        short('p'),
+        long,
+        required_unless_present = "all",
+        conflicts_with = "all"
+    )]
+    pub profile: Option<String>,
+    /// Write every profile
+    #[arg(short('a'), long)]
+    pub all: bool,
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    /// Only report the changes and print the config diff
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Which side wins when a profile was edited in both
+    #[arg(long, value_enum)]
+    pub prefer: Option<Side>,
+    /// Only sync these profiles
+    #[arg(short('p'), long)]
+    pub profile: Vec<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use clap::Parser;
+    use serial_test::serial;
 
     #[test]
     fn test_setup_subcommand() {
         let cli = Cli::try_parse_from(["ssologinlite", "setup"]).unwrap();
-        assert!(matches!(cli.command, Commands::Setup));
+        assert!(matches!(cli.command, Commands::Setup(_)));
         assert!(!cli.debug);
     }
 
+    #[test]
+    fn test_setup_dry_run() {
+        let cli = Cli::try_parse_from(["ssologinlite", "setup", "--dry-run"]).unwrap();
+        match cli.command {
+            Commands::Setup(args) => assert!(args.dry_run),
+            _ => panic!("expected Setup"),
+        }
+        let cli = Cli::try_parse_from(["ssologinlite", "setup"]).unwrap();
+        match cli.command {
+            Commands::Setup(args) => assert!(!args.dry_run),
+            _ => panic!("expected Setup"),
+        }
+    }
+
+    #[test]
+    fn test_unsetup_dry_run() {
+        let cli = Cli::try_parse_from(["ssologinlite", "unsetup", "--dry-run"]).unwrap();
+        match cli.command {
+            Commands::Unsetup(args) => assert!(args.dry_run),
+            _ => panic!("expected Unsetup"),
+        }
+    }
+
+    #[test]
+    fn test_unsetup_subcommand() {
+        let cli = Cli::try_parse_from(["ssologinlite", "unsetup"]).unwrap();
+        assert!(matches!(cli.command, Commands::Unsetup(_)));
+    }
+
     #[test]
     fn test_token_subcommand() {
         let cli = Cli::try_parse_from(["ssologinlite", "token", "--profile", "dev"]).unwrap();
@@ -78,7 +367,7 @@ mod tests {
         let cli = Cli::try_parse_from(["ssologinlite", "eks", "--profile", "prod"]).unwrap();
         match cli.command {
             Commands::Eks(args) => {
-                assert_eq!(args.profile, "prod");
+                assert_eq!(args.profile.as_deref(), Some("prod"));
                 assert!(args.region.is_none());
                 assert!(args.cluster.is_none());
             }
@@ -101,7 +390,7 @@ mod tests {
         .unwrap();
         match cli.command {
             Commands::Eks(args) => {
-                assert_eq!(args.profile, "prod");
+                assert_eq!(args.profile.as_deref(), Some("prod"));
                 assert_eq!(args.region.as_deref(), Some("us-west-2"));
                 assert_eq!(args.cluster.as_deref(), Some("my-cluster"));
             }
@@ -157,7 +446,7 @@ mod tests {
         .unwrap();
         match cli.command {
             Commands::Eks(args) => {
-                assert_eq!(args.profile, "prod");
+                assert_eq!(args.profile.as_deref(), Some("prod"));
                 assert_eq!(args.region.as_deref(), Some("eu-west-1"));
                 assert_eq!(args.cluster.as_deref(), Some("cluster-1"));
             }
@@ -165,22 +454,263 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_write_credentials_profile() {
+        let cli =
+            Cli::try_parse_from(["ssologinlite", "write-credentials", "--profile", "dev"]).unwrap();
+        match cli.command {
+            Commands::WriteCredentials(args) => {
+                assert_eq!(args.profile.as_deref(), Some("dev"));
+                assert!(!args.all);
+            }
+            _ => panic!("expected WriteCredentials"),
+        }
+    }
+
+    #[test]
+    fn test_write_credentials_all() {
+        let cli = Cli::try_parse_from(["ssologinlite", "write-credentials", "--all"]).unwrap();
+        match cli.command {
+            Commands::WriteCredentials(args) => {
+                assert!(args.profile.is_none());
+                assert!(args.all);
+            }
+            _ => panic!("expected WriteCredentials"),
+        }
+    }
+
+    #[test]
+    fn test_write_credentials_requires_profile_or_all() {
+        let result = Cli::try_parse_from(["ssologinlite", "write-credentials"]);
+        assert!(result.is_err());
+        let result =
+            Cli::try_parse_from(["ssologinlite", "write-credentials", "-p", "dev", "--all"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sync_defaults() {
+        let cli = Cli::try_parse_from(["ssologinlite", "sync"]).unwrap();
+        match cli.command {
+            Commands::Sync(args) => {
+                assert!(!args.dry_run);
+                assert!(args.prefer.is_none());
+                assert!(args.profile.is_empty());
+            }
+            _ => panic!("expected Sync"),
+        }
+    }
+
+    #[test]
+    fn test_sync_all_args() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "sync",
+            "--dry-run",
+            "--prefer",
+            "config",
+            "-p",
+            "dev",
+            "--profile",
+            "prod",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Sync(args) => {
+                assert!(args.dry_run);
+                assert_eq!(args.prefer, Some(Side::Config));
+                assert_eq!(args.profile, vec!["dev", "prod"]);
+            }
+            _ => panic!("expected Sync"),
+        }
+    }
+
+    #[test]
+    fn test_sync_invalid_prefer() {
+        let result = Cli::try_parse_from(["ssologinlite", "sync", "--prefer", "both"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_missing_subcommand() {
         let result = Cli::try_parse_from(["ssologinlite"]);
         assert!(result.is_err());
     }
 
+    // Runs f with AWS_PROFILE set to `value` (or unset), restoring it after.
+    fn with_aws_profile(value: Option<&str>, f: impl FnOnce()) {
+        let saved = std::env::var_os("AWS_PROFILE");
+        match value {
+            Some(v) => std::env::set_var("AWS_PROFILE", v),
+            None => std::env::remove_var("AWS_PROFILE"),
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        match saved {
+            Some(v) => std::env::set_var("AWS_PROFILE", v),
+            None => std::env::remove_var("AWS_PROFILE"),
+        }
+        if let Err(payload) = result {
+            std::panic::resume_unwind(payload);
+        }
+    }
+
     #[test]
+    #[serial(env_vars)]
     fn test_token_missing_profile() {
-        let result = Cli::try_parse_from(["ssologinlite", "token"]);
-        assert!(result.is_err());
+        with_aws_profile(None, || {
+            let result = Cli::try_parse_from(["ssologinlite", "token"]);
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_token_profile_from_env() {
+        with_aws_profile(Some("from-env"), || {
+            let cli = Cli::try_parse_from(["ssologinlite", "token"]).unwrap();
+            match cli.command {
+                Commands::Token(args) => assert_eq!(args.profile, "from-env"),
+                _ => panic!("expected Token"),
+            }
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_token_profile_flag_beats_env() {
+        with_aws_profile(Some("from-env"), || {
+            let cli = Cli::try_parse_from(["ssologinlite", "token", "-p", "dev"]).unwrap();
+            match cli.command {
+                Commands::Token(args) => assert_eq!(args.profile, "dev"),
+                _ => panic!("expected Token"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_eks_sts_endpoint() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "-p",
+            "prod",
+            "-c",
+            "main",
+            "--sts-endpoint",
+            "sts.amazonaws.com",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Eks(args) => {
+                assert_eq!(args.sts_endpoint.as_deref(), Some("sts.amazonaws.com"));
+            }
+            _ => panic!("expected Eks"),
+        }
+    }
+
+    #[test]
+    fn test_eks_kubeconfig_subcommand() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "kubeconfig",
+            "--profile",
+            "prod",
+            "--region",
+            "eu-west-1",
+            "--cluster",
+            "main",
+            "--alias",
+            "{profile}-{cluster}",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Eks(EksArgs {
+                command: Some(EksCommands::Kubeconfig(args)),
+                ..
+            }) => {
+                assert_eq!(args.profile, "prod");
+                assert_eq!(args.region.as_deref(), Some("eu-west-1"));
+                assert_eq!(args.cluster.as_deref(), Some("main"));
+                assert!(!args.all);
+                assert_eq!(args.alias.as_deref(), Some("{profile}-{cluster}"));
+                assert!(args.kubeconfig.is_none());
+            }
+            _ => panic!("expected Eks Kubeconfig"),
+        }
     }
 
     #[test]
+    fn test_eks_kubeconfig_all() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "kubeconfig",
+            "-p",
+            "prod",
+            "--all",
+            "--kubeconfig",
+            "/tmp/kube",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Eks(EksArgs {
+                command: Some(EksCommands::Kubeconfig(args)),
+                ..
+            }) => {
+                assert!(args.all);
+                assert!(args.cluster.is_none());
+                assert_eq!(args.kubeconfig, Some("/tmp/kube".into()));
+            }
+            _ => panic!("expected Eks Kubeconfig"),
+        }
+    }
+
+    #[test]
+    fn test_eks_kubeconfig_requires_cluster_or_all() {
+        let none = Cli::try_parse_from(["ssologinlite", "eks", "kubeconfig", "-p", "prod"]);
+        assert!(none.is_err());
+        let both = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "kubeconfig",
+            "-p",
+            "prod",
+            "-c",
+            "main",
+            "--all",
+        ]);
+        assert!(both.is_err());
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_eks_kubeconfig_missing_profile() {
+        with_aws_profile(None, || {
+            let result = Cli::try_parse_from(["ssologinlite", "eks", "kubeconfig", "--all"]);
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
     fn test_eks_missing_profile() {
-        let result = Cli::try_parse_from(["ssologinlite", "eks"]);
-        assert!(result.is_err());
+        with_aws_profile(None, || {
+            let result = Cli::try_parse_from(["ssologinlite", "eks"]);
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_eks_profile_from_env() {
+        with_aws_profile(Some("from-env"), || {
+            let cli = Cli::try_parse_from(["ssologinlite", "eks"]).unwrap();
+            match cli.command {
+                Commands::Eks(args) => assert_eq!(args.profile.as_deref(), Some("from-env")),
+                _ => panic!("expected Eks"),
+            }
+        });
     }
 
     #[test]
@@ -194,4 +724,316 @@ mod tests {
         let cli = Cli::try_parse_from(["ssologinlite", "setup"]).unwrap();
         assert!(!cli.debug);
     }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_eks_context_without_profile() {
+        with_aws_profile(None, || {
+            let cli =
+                Cli::try_parse_from(["ssologinlite", "eks", "--context", "prod-main"]).unwrap();
+            match cli.command {
+                Commands::Eks(args) => {
+                    assert_eq!(args.context.as_deref(), Some("prod-main"));
+                    assert!(args.profile.is_none());
+                    assert!(args.cluster.is_none());
+                }
+                _ => panic!("expected Eks"),
+            }
+        });
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_eks_requires_profile_or_context() {
+        with_aws_profile(None, || {
+            let result = Cli::try_parse_from(["ssologinlite", "eks", "-c", "main"]);
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    fn test_eks_role_arn_and_external_id() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "-p",
+            "prod",
+            "-c",
+            "main",
+            "--role-arn",
+            "arn:aws:iam::123456789012:role/eks-admin",
+            "--external-id",
+            "ext-1",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Eks(args) => {
+                assert_eq!(
+                    args.role_arn.as_deref(),
+                    Some("arn:aws:iam::123456789012:role/eks-admin")
+                );
+                assert_eq!(args.external_id.as_deref(), Some("ext-1"));
+            }
+            _ => panic!("expected Eks"),
+        }
+    }
+
+    #[test]
+    fn test_eks_external_id_requires_role_arn() {
+        let result = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "-p",
+            "prod",
+            "-c",
+            "main",
+            "--external-id",
+            "ext-1",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_eks_doctor() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "eks",
+            "doctor",
+            "--profile",
+            "prod",
+            "--cluster",
+            "main",
+            "--region",
+            "eu-west-1",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Eks(EksArgs {
+                command: Some(EksCommands::Doctor(args)),
+                ..
+            }) => {
+                assert_eq!(args.profile.as_deref(), Some("prod"));
+                assert_eq!(args.cluster.as_deref(), Some("main"));
+                assert_eq!(args.region.as_deref(), Some("eu-west-1"));
+                assert!(args.role_arn.is_none());
+            }
+            _ => panic!("expected eks doctor"),
+        }
+    }
+
+    #[test]
+    #[serial(env_vars)]
+    fn test_eks_doctor_defaults_like_eks() {
+        with_aws_profile(None, || {
+            let cli = Cli::try_parse_from(["ssologinlite", "eks", "doctor", "-p", "prod"]);
+            match cli.unwrap().command {
+                Commands::Eks(EksArgs {
+                    command: Some(EksCommands::Doctor(args)),
+                    ..
+                }) => assert!(args.cluster.is_none()),
+                _ => panic!("expected eks doctor"),
+            }
+            let cli =
+                Cli::try_parse_from(["ssologinlite", "eks", "doctor", "--context", "prod-main"]);
+            match cli.unwrap().command {
+                Commands::Eks(EksArgs {
+                    command: Some(EksCommands::Doctor(args)),
+                    ..
+                }) => {
+                    assert_eq!(args.context.as_deref(), Some("prod-main"));
+                    assert!(args.profile.is_none());
+                }
+                _ => panic!("expected eks doctor"),
+            }
+            assert!(Cli::try_parse_from(["ssologinlite", "eks", "doctor"]).is_err());
+        });
+    }
+
+    #[test]
+    fn test_docker_credential_actions() {
+        for (arg, action) in [
+            ("get", DockerCredentialAction::Get),
+            ("list", DockerCredentialAction::List),
+            ("store", DockerCredentialAction::Store),
+            ("erase", DockerCredentialAction::Erase),
+        ] {
+            let cli = Cli::try_parse_from(["ssologinlite", "docker-credential", arg]).unwrap();
+            match cli.command {
+                Commands::DockerCredential(args) => assert_eq!(args.action, action),
+                _ => panic!("expected DockerCredential"),
+            }
+        }
+        assert!(Cli::try_parse_from(["ssologinlite", "docker-credential", "version"]).is_err());
+    }
+
+    #[test]
+    fn test_resolve_invocation_as_docker_helper() {
+        let args = resolve_invocation(
+            ["/usr/local/bin/docker-credential-ssologinlite", "get"].map(OsString::from),
+        );
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::DockerCredential(args) => {
+                assert_eq!(args.action, DockerCredentialAction::Get)
+            }
+            _ => panic!("expected DockerCredential"),
+        }
+        let args = resolve_invocation(["ssologinlite", "tui"].map(OsString::from));
+        assert_eq!(args, ["ssologinlite", "tui"].map(OsString::from));
+    }
+
+    #[test]
+    fn test_presign() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "presign",
+            "s3://my-bucket/report.csv",
+            "--profile",
+            "prod",
+            "--expires",
+            "1h",
+            "--method",
+            "put",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Presign(args) => {
+                assert_eq!(args.uri, "s3://my-bucket/report.csv");
+                assert_eq!(args.profile, "prod");
+                assert_eq!(args.expires, 3600);
+                assert_eq!(args.method, HttpMethod::Put);
+                assert_eq!(args.addressing, Addressing::Auto);
+                assert!(args.region.is_none());
+            }
+            _ => panic!("expected Presign"),
+        }
+    }
+
+    #[test]
+    fn test_presign_defaults_and_bad_expires() {
+        let base = ["ssologinlite", "presign", "s3://b/k", "-p", "prod"];
+        match Cli::try_parse_from(base).unwrap().command {
+            Commands::Presign(args) => {
+                assert_eq!(args.expires, 3600);
+                assert_eq!(args.method, HttpMethod::Get);
+            }
+            _ => panic!("expected Presign"),
+        }
+        assert!(Cli::try_parse_from(base.iter().chain(&["--expires", "8d"])).is_err());
+    }
+
+    #[test]
+    fn test_codeartifact() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "codeartifact",
+            "-p",
+            "dev",
+            "--domain",
+            "my-domain",
+            "--repository",
+            "my-repo",
+            "--tool",
+            "pip",
+            "--write",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Codeartifact(args) => {
+                assert_eq!(args.profile, "dev");
+                assert_eq!(args.domain, "my-domain");
+                assert!(args.domain_owner.is_none());
+                assert_eq!(args.repository.as_deref(), Some("my-repo"));
+                assert_eq!(args.tool, Some(PackageTool::Pip));
+                assert!(args.write);
+            }
+            _ => panic!("expected Codeartifact"),
+        }
+    }
+
+    #[test]
+    fn test_codeartifact_tool_requires_repository() {
+        let base = [
+            "ssologinlite",
+            "codeartifact",
+            "-p",
+            "dev",
+            "-d",
+            "my-domain",
+        ];
+        assert!(Cli::try_parse_from(base.iter().chain(&["--tool", "npm"])).is_err());
+        assert!(
+            Cli::try_parse_from(base.iter().chain(&["--repository", "my-repo", "--write"]))
+                .is_err()
+        );
+        assert!(Cli::try_parse_from(base).is_ok());
+    }
+
+    #[test]
+    fn test_rds_token() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "rds-token",
+            "-p",
+            "prod",
+            "--host",
+            "db.example.com",
+            "--port",
+            "5432",
+            "--user",
+            "app",
+            "--format",
+            "psql",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::RdsToken(args) => {
+                assert_eq!(args.profile, "prod");
+                assert_eq!(args.host, "db.example.com");
+                assert_eq!(args.port, 5432);
+                assert_eq!(args.user, "app");
+                assert!(args.region.is_none());
+                assert_eq!(args.format, ConnectionFormat::Psql);
+            }
+            _ => panic!("expected RdsToken"),
+        }
+    }
+
+    #[test]
+    fn test_rds_token_defaults_to_bare_token() {
+        let cli = Cli::try_parse_from([
+            "ssologinlite",
+            "rds-token",
+            "-p",
+            "prod",
+            "--host",
+            "db.example.com",
+            "--port",
+            "3306",
+            "-u",
+            "app",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::RdsToken(args) => assert_eq!(args.format, ConnectionFormat::Token),
+            _ => panic!("expected RdsToken"),
+        }
+    }
+
+    #[test]
+    fn test_rds_token_rejects_bad_port() {
+        let result = Cli::try_parse_from([
+            "ssologinlite",
+            "rds-token",
+            "-p",
+            "prod",
+            "--host",
+            "db.example.com",
+            "--port",
+            "70000",
+            "-u",
+            "app",
+        ]);
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,498 @@
+use crate::aws_profile::{config_section_name, Profile, Profiles};
+use crate::config_plan::ConfigPlan;
+use crate::constants::SYNC_BASE;
+use crate::file_helper::{backup_config, get_data_file, get_exe_path, write_file_atomic};
+use anyhow::{anyhow, Result};
+use ini::Ini;
+use log::{debug, error, info};
+use std::collections::BTreeSet;
+
+// Which copy of a profile wins when ~/.aws/config and profiles.json disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Side {
+    Config,
+    File,
+}
+
+// How a profile differs between ~/.aws/config and profiles.json.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileChange {
+    // Only in ~/.aws/config.
+    Added(Profile),
+    // Only in profiles.json.
+    Removed(Profile),
+    Changed { config: Profile, file: Profile },
+    // A ~/.aws/config section still pointing at ssologinlite whose profile
+    // is gone from profiles.json.
+    Orphaned,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncEntry {
+    pub name: String,
+    pub change: ProfileChange,
+    // None is an unresolved conflict: both sides moved away from the last
+    // synced copy.
+    pub winner: Option<Side>,
+}
+
+impl SyncEntry {
+    fn action(&self) -> &'static str {
+        match (&self.change, self.winner) {
+            (ProfileChange::Orphaned, _) => "remove from ~/.aws/config, not in profiles.json",
+            (_, None) => "conflict, edited on both sides",
+            (ProfileChange::Added(_), Some(Side::Config)) => "import into profiles.json",
+            (ProfileChange::Added(_), Some(Side::File)) => "remove from ~/.aws/config",
+            (ProfileChange::Removed(_), Some(Side::Config)) => "remove from profiles.json",
+            (ProfileChange::Removed(_), Some(Side::File)) => "write to ~/.aws/config",
+            (ProfileChange::Changed { .. }, Some(Side::Config)) => "update profiles.json",
+            (ProfileChange::Changed { .. }, Some(Side::File)) => "update ~/.aws/config",
+        }
+    }
+
+    // Field-level differences for a changed profile, as "key: file -> config".
+    pub fn fields(&self) -> Vec<String> {
+        let ProfileChange::Changed { config, file } = &self.change else {
+            return Vec::new();
+        };
+        let (Ok(config), Ok(file)) = (serde_json::to_value(config), serde_json::to_value(file))
+        else {
+            return Vec::new();
+        };
+        // Profiles serialize as {"Variant": {fields}}; compare the inner maps.
+        let inner = |v: &serde_json::Value| v.as_object().and_then(|o| o.values().next()).cloned();
+        let (Some(config), Some(file)) = (inner(&config), inner(&file)) else {
+            return Vec::new();
+        };
+        let (Some(config), Some(file)) = (config.as_object(), file.as_object()) else {
+            return Vec::new();
+        };
+        let keys: BTreeSet<&String> = config.keys().chain(file.keys()).collect();
+        keys.into_iter()
+            .filter(|k| config.get(*k) != file.get(*k))
+            .map(|k| {
+                let show = |v: Option<&serde_json::Value>| match v {
+                    Some(serde_json::Value::String(s)) => s.clone(),
+                    Some(serde_json::Value::Null) | None => "(none)".to_string(),
+                    Some(v) => v.to_string(),
+                };
+                format!("{k}: {} -> {}", show(file.get(k)), show(config.get(k)))
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for SyncEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let marker = match (&self.change, self.winner) {
+            (ProfileChange::Orphaned, _) => '-',
+            (_, None) => '!',
+            (ProfileChange::Added(_), _) => '+',
+            (ProfileChange::Removed(_), _) => '-',
+            (ProfileChange::Changed { .. }, _) => '~',
+        };
+        write!(f, "{marker} {} ({})", self.name, self.action())?;
+        for field in self.fields() {
+            write!(f, "\n    {field}")?;
+        }
+        Ok(())
+    }
+}
+
+// Three-way comparison of ~/.aws/config and profiles.json against the copy
+// saved by the last setup or sync. Without that base, a profile present on
+// one side only is copied to the other and differing profiles are conflicts.
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    pub entries: Vec<SyncEntry>,
+}
+
+impl SyncPlan {
+    // `orphans` are the config sections from_ini found pointing at
+    // ssologinlite with no profile left in profiles.json.
+    pub fn compute(
+        config: &Profiles,
+        orphans: &[String],
+        file: &Profiles,
+        base: Option<&Profiles>,
+    ) -> SyncPlan {
+        let known = |p: &&Profile| !matches!(p, Profile::OtherProfile);
+        let names: BTreeSet<&String> = config
+            .profiles
+            .iter()
+            .chain(file.profiles.iter())
+            .filter(|(_, p)| known(p))
+            .map(|(name, _)| name)
+            .collect();
+        let mut entries = Vec::new();
+        for name in names {
+            let c = config.profiles.get(name).filter(known);
+            let f = file.profiles.get(name).filter(known);
+            let b = base.and_then(|base| base.profiles.get(name));
+            let (change, winner) = match (c, f) {
+                (Some(c), Some(f)) if c == f => continue,
+                (Some(c), Some(f)) => {
+                    let winner = match b {
+                        Some(b) if b == f => Some(Side::Config),
+                        Some(b) if b == c => Some(Side::File),
+                        _ => None,
+                    };
+                    let change = ProfileChange::Changed {
+                        config: c.clone(),
+                        file: f.clone(),
+                    };
+                    (change, winner)
+                }
+                (Some(c), None) => {
+                    let winner = match b {
+                        // profiles.json dropped it and the config still has
+                        // the synced copy.
+                        Some(b) if b == c => Some(Side::File),
+                        Some(_) => None,
+                        None => Some(Side::Config),
+                    };
+                    (ProfileChange::Added(c.clone()), winner)
+                }
+                (None, Some(f)) => {
+                    let winner = match b {
+                        Some(b) if b == f => Some(Side::Config),
+                        Some(_) => None,
+                        None => Some(Side::File),
+                    };
+                    (ProfileChange::Removed(f.clone()), winner)
+                }
+                (None, None) => continue,
+            };
+            entries.push(SyncEntry {
+                name: name.clone(),
+                change,
+                winner,
+            });
+        }
+        // Nothing to import them from, so profiles.json wins.
+        for name in orphans {
+            entries.push(SyncEntry {
+                name: name.clone(),
+                change: ProfileChange::Orphaned,
+                winner: Some(Side::File),
+            });
+        }
+        SyncPlan { entries }
+    }
+
+    // Keep only the named profiles (all of them when `only` is empty) and
+    // settle conflicts in favour of `prefer`.
+    pub fn resolve(mut self, prefer: Option<Side>, only: &[String]) -> SyncPlan {
+        if !only.is_empty() {
+            self.entries.retain(|e| only.contains(&e.name));
+        }
+        for entry in self.entries.iter_mut() {
+            if entry.winner.is_none() {
+                entry.winner = prefer;
+            }
+        }
+        self
+    }
+
+    pub fn conflicts(&self) -> usize {
+        self.entries.iter().filter(|e| e.winner.is_none()).count()
+    }
+
+    pub fn apply_to(&self, profiles: &mut Profiles, conf: &mut Ini, exe_path: &str) {
+        for entry in self.entries.iter() {
+            debug!("profile_sync.SyncPlan.apply_to {}", entry);
+            let name = &entry.name;
+            match (&entry.change, entry.winner) {
+                (_, None) => {}
+                (ProfileChange::Orphaned, Some(_)) => {
+                    conf.delete(Some(config_section_name(name)));
+                }
+                (ProfileChange::Added(c), Some(Side::Config))
+                | (ProfileChange::Changed { config: c, .. }, Some(Side::Config)) => {
+                    profiles.profiles.insert(name.clone(), c.clone());
+                    c.set_managed_section(conf, name, exe_path);
+                }
+                (ProfileChange::Removed(_), Some(Side::Config)) => {
+                    profiles.profiles.remove(name);
+                }
+                (ProfileChange::Added(_), Some(Side::File)) => {
+                    conf.delete(Some(config_section_name(name)));
+                }
+                (ProfileChange::Removed(f), Some(Side::File))
+                | (ProfileChange::Changed { file: f, .. }, Some(Side::File)) => {
+                    f.set_managed_section(conf, name, exe_path);
+                }
+            }
+        }
+    }
+
+    pub fn load() -> Result<(SyncPlan, Profiles, ConfigPlan)> {
+        info!("Comparing aws config with profiles file");
+        let file = Profiles::from_file()?;
+        let (config, orphans) = Profiles::from_existing_config()?;
+        let base = load_base();
+        let plan = SyncPlan::compute(&config, &orphans, &file, base.as_ref());
+        Ok((plan, file, ConfigPlan::load_aws_config()?))
+    }
+
+    // Apply the resolved entries to both files and return the config plan
+    // so the caller can show a diff. With dry_run nothing is written.
+    pub fn apply(
+        &self,
+        mut file: Profiles,
+        config: ConfigPlan,
+        dry_run: bool,
+    ) -> Result<ConfigPlan> {
+        let exe_path_os_str = get_exe_path()?;
+        let exe_path = match exe_path_os_str.to_str() {
+            Some(exe_path) => exe_path,
+            None => {
+                return Err(anyhow!("Could not get exe path"));
+            }
+        };
+        let mut profiles = file.clone();
+        let config = config.edit(|conf| {
+            self.apply_to(&mut profiles, conf, exe_path);
+            Ok(())
+        })?;
+        if dry_run {
+            return Ok(config);
+        }
+        backup_config()?;
+        if profiles.profiles != file.profiles {
+            file = profiles;
+            file.to_file()?;
+        }
+        config.apply()?;
+        save_base(&file)?;
+        Ok(config)
+    }
+}
+
+fn base_path() -> Result<std::ffi::OsString> {
+    get_data_file(SYNC_BASE)
+}
+
+fn load_base() -> Option<Profiles> {
+    let path = base_path().ok()?;
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) => {
+            debug!("profile_sync.load_base {}", e);
+            return None;
+        }
+    };
+    match Profiles::from_json(&data) {
+        Ok((base, _)) => Some(base),
+        Err(e) => {
+            error!("profile_sync.load_base {}", e);
+            None
+        }
+    }
+}
+
+// Remember the profiles both files agree on, so the next sync can tell which
+// side was edited.
+pub fn save_base(profiles: &Profiles) -> Result<()> {
+    write_file_atomic(&base_path()?, profiles.to_json()?.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_profile::{AssumeSsoProfile, SsoProfile};
+    use std::collections::HashMap;
+
+    fn sso(name: &str, region: &str) -> Profile {
+        Profile::SsoProfile(SsoProfile {
+            profile_name: name.to_string(),
+            sso_start_url: "https://my-sso.awsapps.com/start".to_string(),
+            sso_region: "us-west-2".to_string(),
+            sso_account_id: "123456789012".to_string(),
+            sso_role_name: "AdminRole".to_string(),
+            region: Some(region.to_string()),
+            duration_seconds: None,
+            eks_clusters: Vec::new(),
+        })
+    }
+
+    fn assume(name: &str) -> Profile {
+        Profile::AssumeSsoProfile(AssumeSsoProfile {
+            source_profile: "dev".to_string(),
+            profile_name: name.to_string(),
+            role_arn: "arn:aws:iam::123456789012:role/MyRole".to_string(),
+            region: "us-east-1".to_string(),
+            eks_clusters: Vec::new(),
+        })
+    }
+
+    fn profiles(list: &[Profile]) -> Profiles {
+        let mut profiles = HashMap::new();
+        for p in list {
+            let name = match p {
+                Profile::SsoProfile(p) => p.profile_name.clone(),
+                Profile::AssumeSsoProfile(p) => p.profile_name.clone(),
+                Profile::OtherProfile => "other".to_string(),
+            };
+            profiles.insert(name, p.clone());
+        }
+        Profiles { profiles }
+    }
+
+    fn entry<'a>(plan: &'a SyncPlan, name: &str) -> &'a SyncEntry {
+        plan.entries.iter().find(|e| e.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_compute_identical_is_empty() {
+        let p = profiles(&[sso("dev", "us-west-2"), assume("prod")]);
+        assert!(SyncPlan::compute(&p, &[], &p, None).entries.is_empty());
+    }
+
+    #[test]
+    fn test_compute_without_base() {
+        let config = profiles(&[sso("dev", "eu-west-1"), sso("new", "us-west-2")]);
+        let file = profiles(&[sso("dev", "us-west-2"), assume("prod")]);
+        let plan = SyncPlan::compute(&config, &[], &file, None);
+        assert_eq!(plan.entries.len(), 3);
+        assert_eq!(entry(&plan, "new").winner, Some(Side::Config));
+        assert!(matches!(
+            entry(&plan, "new").change,
+            ProfileChange::Added(_)
+        ));
+        assert_eq!(entry(&plan, "prod").winner, Some(Side::File));
+        assert!(matches!(
+            entry(&plan, "prod").change,
+            ProfileChange::Removed(_)
+        ));
+        assert_eq!(entry(&plan, "dev").winner, None);
+        assert_eq!(plan.conflicts(), 1);
+    }
+
+    #[test]
+    fn test_compute_with_base_picks_edited_side() {
+        let base = profiles(&[sso("a", "us-west-2"), sso("b", "us-west-2"), assume("c")]);
+        // a edited in the config, b edited in profiles.json (the TUI), c
+        // deleted from the config.
+        let config = profiles(&[sso("a", "eu-west-1"), sso("b", "us-west-2")]);
+        let file = profiles(&[sso("a", "us-west-2"), sso("b", "ap-south-1"), assume("c")]);
+        let plan = SyncPlan::compute(&config, &[], &file, Some(&base));
+        assert_eq!(entry(&plan, "a").winner, Some(Side::Config));
+        assert_eq!(entry(&plan, "b").winner, Some(Side::File));
+        assert_eq!(entry(&plan, "c").winner, Some(Side::Config));
+        assert_eq!(plan.conflicts(), 0);
+    }
+
+    #[test]
+    fn test_compute_with_base_detects_conflict() {
+        let base = profiles(&[sso("dev", "us-west-2")]);
+        let config = profiles(&[sso("dev", "eu-west-1")]);
+        let file = profiles(&[sso("dev", "ap-south-1")]);
+        let plan = SyncPlan::compute(&config, &[], &file, Some(&base));
+        assert_eq!(plan.conflicts(), 1);
+        assert_eq!(
+            entry(&plan, "dev").fields(),
+            vec!["region: ap-south-1 -> eu-west-1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_compute_ignores_other_profiles() {
+        let config = Profiles::default();
+        let file = profiles(&[Profile::OtherProfile]);
+        assert!(SyncPlan::compute(&config, &[], &file, None)
+            .entries
+            .is_empty());
+    }
+
+    #[test]
+    fn test_resolve_prefer_and_only() {
+        let config = profiles(&[sso("dev", "eu-west-1"), sso("new", "us-west-2")]);
+        let file = profiles(&[sso("dev", "us-west-2")]);
+        let plan = SyncPlan::compute(&config, &[], &file, None);
+        let resolved = plan.clone().resolve(Some(Side::File), &[]);
+        assert_eq!(resolved.conflicts(), 0);
+        assert_eq!(entry(&resolved, "dev").winner, Some(Side::File));
+        let only = plan.resolve(None, &["new".to_string()]);
+        assert_eq!(only.entries.len(), 1);
+        assert_eq!(only.entries[0].name, "new");
+    }
+
+    #[test]
+    fn test_apply_to() {
+        let base = profiles(&[sso("a", "us-west-2"), sso("b", "us-west-2"), assume("c")]);
+        let config = profiles(&[sso("a", "eu-west-1"), sso("b", "us-west-2"), sso("d", "x")]);
+        let file = profiles(&[sso("a", "us-west-2"), sso("b", "ap-south-1"), assume("c")]);
+        let plan = SyncPlan::compute(&config, &[], &file, Some(&base));
+
+        let mut conf = Ini::load_from_str(
+            "[profile a]\nregion = eu-west-1\ncredential_process = /bin/ssologinlite token --profile a\n\n\
+             [profile b]\nregion = us-west-2\ncredential_process = /bin/ssologinlite token --profile b\n\n\
+             [profile d]\nsso_start_url = https://my-sso.awsapps.com/start\nsso_region = us-west-2\n\
+             sso_account_id = 123456789012\nsso_role_name = AdminRole\nregion = x\n",
+        )
+        .unwrap();
+        let mut result = file.clone();
+        plan.apply_to(&mut result, &mut conf, "/bin/ssologinlite");
+
+        assert_eq!(result.profiles["a"], sso("a", "eu-west-1"));
+        assert_eq!(result.profiles["b"], sso("b", "ap-south-1"));
+        assert_eq!(result.profiles["d"], sso("d", "x"));
+        assert!(!result.profiles.contains_key("c"));
+        assert_eq!(
+            conf.section(Some("profile b")).unwrap().get("region"),
+            Some("ap-south-1")
+        );
+        let d = conf.section(Some("profile d")).unwrap();
+        assert_eq!(
+            d.get("credential_process"),
+            Some("/bin/ssologinlite token --profile d")
+        );
+        assert!(!d.contains_key("sso_start_url"));
+        // Applying again finds nothing left to do.
+        let (again, orphans) = Profiles::from_ini(&conf, &result).unwrap();
+        assert!(SyncPlan::compute(&again, &orphans, &result, None)
+            .entries
+            .is_empty());
+    }
+
+    #[test]
+    fn test_profile_deleted_from_file_after_sync() {
+        let base = profiles(&[sso("a", "us-west-2"), sso("b", "us-west-2")]);
+        let mut conf = Ini::new();
+        for (name, profile) in base.profiles.iter() {
+            profile.set_managed_section(&mut conf, name, "/bin/ssologinlite");
+        }
+        // b removed from profiles.json, e.g. in the TUI.
+        let file = profiles(&[sso("a", "us-west-2")]);
+        let (config, orphans) = Profiles::from_ini(&conf, &file).unwrap();
+        assert_eq!(orphans, vec!["b".to_string()]);
+        let plan = SyncPlan::compute(&config, &orphans, &file, Some(&base));
+        assert_eq!(plan.entries.len(), 1);
+        assert_eq!(entry(&plan, "b").change, ProfileChange::Orphaned);
+        assert_eq!(
+            format!("{}", plan.entries[0]),
+            "- b (remove from ~/.aws/config, not in profiles.json)"
+        );
+
+        let mut result = file.clone();
+        plan.apply_to(&mut result, &mut conf, "/bin/ssologinlite");
+        assert!(conf.section(Some("profile b")).is_none());
+        assert!(conf.section(Some("profile a")).is_some());
+        let (again, orphans) = Profiles::from_ini(&conf, &result).unwrap();
+        assert!(orphans.is_empty());
+        assert!(SyncPlan::compute(&again, &orphans, &result, Some(&file))
+            .entries
+            .is_empty());
+    }
+
+    #[test]
+    fn test_entry_display() {
+        let config = profiles(&[sso("new", "us-west-2")]);
+        let plan = SyncPlan::compute(&config, &[], &Profiles::default(), None);
+        assert_eq!(
+            format!("{}", plan.entries[0]),
+            "+ new (import into profiles.json)"
+        );
+    }
+}
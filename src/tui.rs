@@ -21,16 +21,16 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
-use crate::aws_profile::{AssumeSsoProfile, Profile, Profiles, SsoProfile};
-use crate::config::ProgramConfig;
-use crate::constants::{CONFIG_FILE, PROFILES, PROGRAM_FOLDER};
-use crate::file_helper::{
-    get_aws_config, get_exe_path, get_home_os_string, restrict_file_permissions,
+use crate::aws_profile::{
+    config_section_name, AssumeSsoProfile, EksClusterDefault, Profile, Profiles, SsoProfile,
 };
+use crate::config::ProgramConfig;
+use crate::config_plan::ConfigPlan;
+use crate::file_helper::{get_data_file, get_exe_path, get_program_config, write_file_atomic};
 
 type Term = Terminal<CrosstermBackend<Stdout>>;
 
-const SSO_FIELD_LABELS: [&str; 7] = [
+const SSO_FIELD_LABELS: [&str; 8] = [
     "Profile name",
     "SSO start URL",
     "SSO region",
@@ -38,9 +38,16 @@ const SSO_FIELD_LABELS: [&str; 7] = [
     "SSO role name",
     "Default region",
     "Duration (sec)",
+    "EKS clusters",
 ];
 
-const ASSUME_FIELD_LABELS: [&str; 4] = ["Profile name", "Source profile", "Role ARN", "Region"];
+const ASSUME_FIELD_LABELS: [&str; 5] = [
+    "Profile name",
+    "Source profile",
+    "Role ARN",
+    "Region",
+    "EKS clusters",
+];
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum ProfileKind {
@@ -112,15 +119,15 @@ impl FormMode {
 struct AddForm {
     mode: FormMode,
     kind: ProfileKind,
-    sso_values: [String; 7],
-    assume_values: [String; 4],
+    sso_values: [String; 8],
+    assume_values: [String; 5],
     focused: usize,
     available_sources: Vec<String>,
 }
 
 impl AddForm {
     fn new(available_sources: Vec<String>) -> Self {
-        let mut assume_values: [String; 4] = Default::default();
+        let mut assume_values: [String; 5] = Default::default();
         if let Some(first) = available_sources.first() {
             assume_values[ASSUME_SOURCE_FIELD] = first.clone();
         }
@@ -143,7 +150,7 @@ impl AddForm {
     ) -> Option<Self> {
         let (kind, sso_values, assume_values) = match profile {
             Profile::SsoProfile(p) => {
-                let values: [String; 7] = [
+                let values: [String; 8] = [
                     p.profile_name.clone(),
                     p.sso_start_url.clone(),
                     p.sso_region.clone(),
@@ -153,15 +160,17 @@ impl AddForm {
                     p.duration_seconds
                         .map(|d| d.to_string())
                         .unwrap_or_default(),
+                    EksClusterDefault::format_list(&p.eks_clusters),
                 ];
                 (ProfileKind::Sso, values, Default::default())
             }
             Profile::AssumeSsoProfile(p) => {
-                let values: [String; 4] = [
+                let values: [String; 5] = [
                     p.profile_name.clone(),
                     p.source_profile.clone(),
                     p.role_arn.clone(),
                     p.region.clone(),
+                    EksClusterDefault::format_list(&p.eks_clusters),
                 ];
                 (ProfileKind::AssumeSso, Default::default(), values)
             }
@@ -280,6 +289,7 @@ impl AddForm {
         let role = self.sso_values[4].trim();
         let region = self.sso_values[5].trim();
         let duration = self.sso_values[6].trim();
+        let eks_clusters = EksClusterDefault::parse_list(&self.sso_values[7])?;
 
         if name.is_empty() {
             return Err(anyhow!("Profile name is required"));
@@ -318,6 +328,7 @@ impl AddForm {
                 Some(region.to_string())
             },
             duration_seconds,
+            eks_clusters,
         })
     }
 
@@ -326,6 +337,7 @@ impl AddForm {
         let source = self.assume_values[ASSUME_SOURCE_FIELD].trim();
         let arn = self.assume_values[2].trim();
         let region = self.assume_values[3].trim();
+        let eks_clusters = EksClusterDefault::parse_list(&self.assume_values[4])?;
 
         if name.is_empty() {
             return Err(anyhow!("Profile name is required"));
@@ -355,6 +367,7 @@ impl AddForm {
             source_profile: source.to_string(),
             role_arn: arn.to_string(),
             region: region.to_string(),
+            eks_clusters,
         })
     }
 }
@@ -365,17 +378,34 @@ enum Screen {
     Add,
     Config(ConfigForm),
     Test(TestRun),
+    Confirm(Box<PendingWrite>),
+}
+
+// A profiles.json update plus the matching ~/.aws/config edits, held on the
+// confirmation screen until the user has seen the diff. Nothing is written
+// until App::commit.
+struct PendingWrite {
+    profiles: Profiles,
+    plan: ConfigPlan,
+    diff: String,
+    message: String,
+    select: Option<String>,
+    // Esc returns to the add/edit form (with its values intact) rather than
+    // the list when the write came from save_form.
+    from_form: bool,
+    scroll: u16,
 }
 
 const CONFIG_FIELD_LABELS: [&str; 2] = ["browser", "default_sso_url"];
 
 // Edits the persistent program config at ~/.config/ssologinlite.toml. The
-// file has two optional string keys (browser, default_sso_url); we treat an
-// empty input string as Option::None on save.
+// form edits two optional string keys (browser, default_sso_url); we treat an
+// empty input string as Option::None on save. Other keys are kept as loaded.
 struct ConfigForm {
     fields: [String; 2],
     focused: usize,
     error: Option<String>,
+    loaded: ProgramConfig,
 }
 
 impl ConfigForm {
@@ -383,11 +413,12 @@ impl ConfigForm {
         let loaded = read_program_config_toml().unwrap_or_default();
         ConfigForm {
             fields: [
-                loaded.browser.unwrap_or_default(),
-                loaded.default_sso_url.unwrap_or_default(),
+                loaded.browser.clone().unwrap_or_default(),
+                loaded.default_sso_url.clone().unwrap_or_default(),
             ],
             focused: 0,
             error: None,
+            loaded,
         }
     }
 
@@ -415,6 +446,7 @@ impl ConfigForm {
         ProgramConfig {
             browser: some_or_none(&self.fields[0]),
             default_sso_url: some_or_none(&self.fields[1]),
+            ..self.loaded.clone()
         }
     }
 }
@@ -518,6 +550,7 @@ impl App {
             Screen::Add => self.handle_add(key),
             Screen::Config(_) => self.handle_config(key),
             Screen::Test(_) => self.handle_test(key),
+            Screen::Confirm(_) => self.handle_confirm(key),
         }
     }
 
@@ -637,21 +670,73 @@ impl App {
             .profiles
             .insert("default".to_string(), default_profile);
 
-        if let Err(e) = save_profiles_to_file(&profiles) {
+        let plan = match ConfigPlan::load_aws_config()
+            .and_then(|plan| plan.edit(|conf| set_profile_section(conf, "default")))
+        {
+            Ok(plan) => plan,
+            Err(e) => {
+                self.status = Some((format!("failed to plan [default]: {e}"), true));
+                return;
+            }
+        };
+        let message = if had_default {
+            format!("replaced default profile with a copy of '{name}'")
+        } else {
+            format!("set '{name}' as the default profile")
+        };
+        self.stage(profiles, plan, message, None, false);
+    }
+
+    // Show the confirmation screen for a pending write, or commit straight
+    // away when it leaves ~/.aws/config unchanged.
+    fn stage(
+        &mut self,
+        profiles: Profiles,
+        plan: ConfigPlan,
+        message: String,
+        select: Option<String>,
+        from_form: bool,
+    ) {
+        let diff = match plan.diff() {
+            Ok(diff) => diff,
+            Err(e) => {
+                self.status = Some((format!("failed to diff ~/.aws/config: {e}"), true));
+                return;
+            }
+        };
+        let pending = PendingWrite {
+            profiles,
+            plan,
+            diff,
+            message,
+            select,
+            from_form,
+            scroll: 0,
+        };
+        if pending.plan.is_empty() {
+            self.commit(pending);
+        } else {
+            self.screen = Screen::Confirm(Box::new(pending));
+        }
+    }
+
+    fn commit(&mut self, pending: PendingWrite) {
+        self.screen = Screen::List;
+        if let Err(e) = pending.profiles.to_file() {
             self.status = Some((format!("failed to save profiles: {e}"), true));
             return;
         }
-        if let Err(e) = write_profile_to_aws_config("default") {
-            self.status = Some((format!("failed to write [default]: {e}"), true));
+        if let Err(e) = pending.plan.apply() {
+            self.status = Some((format!("failed to write ~/.aws/config: {e}"), true));
             return;
         }
         self.refresh();
-        let msg = if had_default {
-            format!("replaced default profile with a copy of '{name}'")
-        } else {
-            format!("set '{name}' as the default profile")
-        };
-        self.status = Some((msg, false));
+        if let Some(name) = &pending.select {
+            if let Some(i) = self.profile_names.iter().position(|n| n == name) {
+                self.list_state.select(Some(i));
+            }
+        }
+        self.status = Some((pending.message, false));
     }
 
     fn start_edit_selected(&mut self) {
@@ -712,23 +797,9 @@ impl App {
                 }
             }
             KeyCode::Enter => {
-                let was_edit = self.form.mode.is_edit();
-                match self.save_form() {
-                    Ok(name) => {
-                        self.refresh();
-                        if let Some(i) = self.profile_names.iter().position(|n| n == &name) {
-                            self.list_state.select(Some(i));
-                        }
-                        let verb = if was_edit { "updated" } else { "saved" };
-                        self.status = Some((
-                            format!("{verb} profile '{name}' — press 't' to test it"),
-                            false,
-                        ));
-                        self.screen = Screen::List;
-                    }
-                    Err(e) => {
-                        self.status = Some((format!("{e}"), true));
-                    }
+                self.status = None;
+                if let Err(e) = self.save_form() {
+                    self.status = Some((format!("{e}"), true));
                 }
             }
             KeyCode::Char(' ') if self.form.focused == 0 => self.form.toggle_kind(),
@@ -762,10 +833,8 @@ impl App {
                 let cfg = form.to_program_config();
                 match write_program_config_toml(&cfg) {
                     Ok(()) => {
-                        self.status = Some((
-                            "config saved to ~/.config/ssologinlite.toml".to_string(),
-                            false,
-                        ));
+                        self.status =
+                            Some((format!("config saved to {}", config_toml_display()), false));
                         self.screen = Screen::List;
                     }
                     Err(e) => {
@@ -781,6 +850,37 @@ impl App {
         Ok(false)
     }
 
+    fn handle_confirm(&mut self, key: KeyEvent) -> Result<bool> {
+        let Screen::Confirm(pending) = &mut self.screen else {
+            return Ok(false);
+        };
+        match key.code {
+            KeyCode::Enter | KeyCode::Char('y') => {
+                let Screen::Confirm(pending) = std::mem::replace(&mut self.screen, Screen::List)
+                else {
+                    return Ok(false);
+                };
+                self.commit(*pending);
+            }
+            KeyCode::Esc | KeyCode::Char('n') => {
+                self.screen = if pending.from_form {
+                    Screen::Add
+                } else {
+                    Screen::List
+                };
+                self.status = Some(("cancelled — nothing was written".to_string(), true));
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                pending.scroll = pending.scroll.saturating_add(1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                pending.scroll = pending.scroll.saturating_sub(1);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
     fn handle_test(&mut self, key: KeyEvent) -> Result<bool> {
         let running = matches!(&self.screen, Screen::Test(r) if r.finished.is_none());
         match key.code {
@@ -833,7 +933,8 @@ impl App {
         matches!(&self.screen, Screen::Test(r) if r.finished.is_none())
     }
 
-    fn save_form(&mut self) -> Result<String> {
+    fn save_form(&mut self) -> Result<()> {
+        let was_edit = self.form.mode.is_edit();
         let new_profile = self.form.validate()?;
         let new_name = new_profile.name().to_string();
         let mut profiles = self.profiles.clone();
@@ -871,14 +972,19 @@ impl App {
         profiles
             .profiles
             .insert(new_name.clone(), new_profile.into_profile());
-        save_profiles_to_file(&profiles)?;
-        // Drop the old entry from ~/.aws/config so a renamed profile doesn't
-        // leave a stale credential_process line pointing at a dead key.
-        if let Some(old) = renamed_from {
-            let _ = remove_profile_from_aws_config(&old);
-        }
-        write_profile_to_aws_config(&new_name)?;
-        Ok(new_name)
+        let plan = ConfigPlan::load_aws_config()?.edit(|conf| {
+            // Drop the old entry from ~/.aws/config so a renamed profile
+            // doesn't leave a stale credential_process line pointing at a
+            // dead key.
+            if let Some(old) = &renamed_from {
+                conf.delete(Some(config_section_name(old)));
+            }
+            set_profile_section(conf, &new_name)
+        })?;
+        let verb = if was_edit { "updated" } else { "saved" };
+        let message = format!("{verb} profile '{new_name}' — press 't' to test it");
+        self.stage(profiles, plan, message, Some(new_name), true);
+        Ok(())
     }
 
     // Spawn `aws sts get-caller-identity --profile <name>` with piped stdio
@@ -964,9 +1070,52 @@ fn render(app: &mut App, f: &mut Frame) {
         Screen::Add => render_add(app, f),
         Screen::Config(form) => render_config(form, f),
         Screen::Test(run) => render_test(run, f),
+        Screen::Confirm(pending) => render_confirm(pending, f),
     }
 }
 
+fn render_confirm(pending: &PendingWrite, f: &mut Frame) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)].as_ref())
+        .split(f.area());
+
+    let lines: Vec<Line> = pending
+        .diff
+        .lines()
+        .map(|line| {
+            let style = if line.starts_with("+++") || line.starts_with("---") {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else if line.starts_with('+') {
+                Style::default().fg(Color::Green)
+            } else if line.starts_with('-') {
+                Style::default().fg(Color::Red)
+            } else if line.starts_with("@@") {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            Line::from(Span::styled(line.to_string(), style))
+        })
+        .collect();
+    let n = pending.plan.changes().len();
+    let title = format!(
+        " confirm {n} change{} to ~/.aws/config ",
+        if n == 1 { "" } else { "s" }
+    );
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .scroll((pending.scroll, 0)),
+        chunks[0],
+    );
+    f.render_widget(
+        Paragraph::new("[Enter / y] write  [↑↓/jk] scroll  [Esc / n] cancel")
+            .block(Block::default().borders(Borders::ALL).title(" help ")),
+        chunks[1],
+    );
+}
+
 fn render_config(form: &ConfigForm, f: &mut Frame) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -976,7 +1125,7 @@ fn render_config(form: &ConfigForm, f: &mut Frame) {
     let mut lines: Vec<Line> = Vec::with_capacity(CONFIG_FIELD_LABELS.len() + 4);
     lines.push(Line::from(""));
     lines.push(Line::from(vec![Span::styled(
-        "  ~/.config/ssologinlite.toml",
+        format!("  {}", config_toml_display()),
         Style::default().fg(Color::DarkGray),
     )]));
     lines.push(Line::from(""));
@@ -1351,6 +1500,7 @@ fn sso_profile_lines(p: &SsoProfile) -> Vec<Line<'static>> {
             &p.duration_seconds
                 .map_or("(default)".to_string(), |d| format!("{d} seconds")),
         ),
+        kv_line("EKS clusters", &eks_clusters_value(&p.eks_clusters)),
     ]
 }
 
@@ -1361,9 +1511,18 @@ fn assume_profile_lines(p: &AssumeSsoProfile) -> Vec<Line<'static>> {
         kv_line("Source profile", &p.source_profile),
         kv_line("Role ARN", &p.role_arn),
         kv_line("Region", &p.region),
+        kv_line("EKS clusters", &eks_clusters_value(&p.eks_clusters)),
     ]
 }
 
+fn eks_clusters_value(clusters: &[EksClusterDefault]) -> String {
+    if clusters.is_empty() {
+        "(none)".to_string()
+    } else {
+        EksClusterDefault::format_list(clusters)
+    }
+}
+
 fn kv_line(key: &str, value: &str) -> Line<'static> {
     Line::from(vec![
         Span::styled(
@@ -1375,44 +1534,17 @@ fn kv_line(key: &str, value: &str) -> Line<'static> {
 }
 
 // === I/O helpers ===
-//
-// Note: this POC bypasses Profiles::to_file because that helper calls
-// restrict_file_permissions before writing, which errors when the file does
-// not yet exist (separate fix in PR #3). We write directly here and chmod
-// best-effort so a fresh install can save its first profile from the TUI.
-
-fn save_profiles_to_file(profiles: &Profiles) -> Result<()> {
-    let path = get_home_os_string(format!("{PROGRAM_FOLDER}/{PROFILES}").as_str())?;
-    if let Some(parent) = std::path::Path::new(&path).parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    let json = serde_json::to_string_pretty(profiles)?;
-    std::fs::write(&path, json)?;
-    let _ = restrict_file_permissions(&path);
-    Ok(())
-}
 
-fn write_profile_to_aws_config(profile_name: &str) -> Result<()> {
-    let aws_config = get_aws_config()?;
+fn set_profile_section(conf: &mut Ini, profile_name: &str) -> Result<()> {
     let exe_path_os = get_exe_path()?;
     let exe_path = exe_path_os
         .to_str()
         .ok_or_else(|| anyhow!("exe path is not valid UTF-8"))?;
-
-    if let Some(parent) = std::path::Path::new(&aws_config).parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    let mut conf = Ini::load_from_file(aws_config.as_os_str()).unwrap_or_default();
-    let section = if profile_name == "default" {
-        "default".to_string()
-    } else {
-        format!("profile {profile_name}")
-    };
+    let section = config_section_name(profile_name);
     let credential_process = format!("{exe_path} token --profile {profile_name}");
     conf.with_section(Some(&section))
         .set("credential_process", credential_process.as_str())
         .set("output", "json");
-    conf.write_to_file(aws_config.as_os_str())?;
     Ok(())
 }
 
@@ -1431,39 +1563,37 @@ fn read_program_config_toml() -> Result<ProgramConfig> {
 }
 
 fn write_program_config_toml(cfg: &ProgramConfig) -> Result<()> {
-    let path = config_toml_path()?;
-    if let Some(parent) = std::path::Path::new(&path).parent() {
-        std::fs::create_dir_all(parent)?;
-    }
     let serialised = toml::to_string_pretty(cfg)?;
-    std::fs::write(&path, serialised)?;
-    Ok(())
+    write_file_atomic(&config_toml_path()?, serialised.as_bytes())
 }
 
 fn config_toml_path() -> Result<std::ffi::OsString> {
-    // CONFIG_FILE is ".config/ssologinlite" (no extension) because the config
-    // crate's File::with_name auto-resolves the extension on read. For
-    // writing, we pin to .toml since that's the format we serialise.
-    let mut path = std::path::PathBuf::from(get_home_os_string(CONFIG_FILE)?);
+    // The program config path has no extension because the config crate's
+    // File::with_name auto-resolves the extension on read. For writing, we
+    // pin to .toml since that's the format we serialise.
+    let mut path = std::path::PathBuf::from(get_program_config()?);
     path.set_extension("toml");
     Ok(path.into_os_string())
 }
 
+fn config_toml_display() -> String {
+    match config_toml_path() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => "~/.config/ssologinlite.toml".to_string(),
+    }
+}
+
 // Serialise the in-memory Profiles to a vanilla AWS CLI config file —
 // SSO and assume-role sections written with their native keys, no
 // credential_process line. Useful as a portable backup or for switching
 // off ssologinlite without losing the profile catalogue.
 //
-// Writes to ~/.aws/ssologinlite/config.exported.<YYYYMMDDTHHMMSS> at 0o600.
+// Writes config.exported.<YYYYMMDDTHHMMSS> to the data directory at 0o600.
 // Returns the path so the TUI can show it in the status bar.
 fn export_aws_config(profiles: &Profiles) -> Result<std::path::PathBuf> {
     let timestamp = Local::now().format("%Y%m%dT%H%M%S").to_string();
-    let path_os =
-        get_home_os_string(format!("{PROGRAM_FOLDER}/config.exported.{timestamp}").as_str())?;
+    let path_os = get_data_file(format!("config.exported.{timestamp}").as_str())?;
     let path = std::path::PathBuf::from(&path_os);
-    if let Some(parent) = path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
 
     let mut conf = Ini::new();
     // Sort by name so re-running export with the same profile set gives
@@ -1472,63 +1602,21 @@ fn export_aws_config(profiles: &Profiles) -> Result<std::path::PathBuf> {
     sorted.sort_by(|a, b| a.0.cmp(b.0));
 
     for (name, profile) in sorted {
-        // The default profile uses [default], not [profile default], in
-        // ~/.aws/config.
-        let section = if name == "default" {
-            "default".to_string()
-        } else {
-            format!("profile {name}")
-        };
-        match profile {
-            Profile::SsoProfile(p) => {
-                let mut sec = conf.with_section(Some(&section));
-                sec.set("sso_start_url", p.sso_start_url.as_str())
-                    .set("sso_region", p.sso_region.as_str())
-                    .set("sso_account_id", p.sso_account_id.as_str())
-                    .set("sso_role_name", p.sso_role_name.as_str());
-                if let Some(r) = &p.region {
-                    sec.set("region", r.as_str());
-                }
-                if let Some(d) = p.duration_seconds {
-                    sec.set("duration_seconds", d.to_string().as_str());
-                }
-                sec.set("output", "json");
-            }
-            Profile::AssumeSsoProfile(p) => {
-                conf.with_section(Some(&section))
-                    .set("source_profile", p.source_profile.as_str())
-                    .set("role_arn", p.role_arn.as_str())
-                    .set("region", p.region.as_str())
-                    .set("output", "json");
-            }
-            Profile::OtherProfile => {
-                // No fields to round-trip — skip.
-                continue;
-            }
+        if matches!(profile, Profile::OtherProfile) {
+            // No fields to round-trip — skip.
+            continue;
         }
+        let section = config_section_name(name);
+        profile.set_native_section(&mut conf, &section);
+        conf.with_section(Some(&section)).set("output", "json");
     }
 
-    conf.write_to_file(&path)?;
-    let _ = restrict_file_permissions(&path_os);
+    let mut data = Vec::new();
+    conf.write_to(&mut data)?;
+    write_file_atomic(&path_os, &data)?;
     Ok(path)
 }
 
-fn remove_profile_from_aws_config(profile_name: &str) -> Result<()> {
-    let aws_config = get_aws_config()?;
-    let mut conf = match Ini::load_from_file(aws_config.as_os_str()) {
-        Ok(c) => c,
-        Err(_) => return Ok(()), // nothing to remove
-    };
-    let section = if profile_name == "default" {
-        "default".to_string()
-    } else {
-        format!("profile {profile_name}")
-    };
-    conf.delete(Some(section.as_str()));
-    conf.write_to_file(aws_config.as_os_str())?;
-    Ok(())
-}
-
 // === TUI lifecycle ===
 
 fn setup_tui() -> Result<Term> {